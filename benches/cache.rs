@@ -0,0 +1,58 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use kong_scraper::{
+    cache::{CacheFormat, CacheStore},
+    kong_data::{Cached, Marketplace, Sale, SaleType},
+    payment::PaymentToken,
+};
+use std::env;
+
+// Every token gets this many sales to approximate a cache carrying sale history.
+const SALES_PER_TOKEN: u64 = 20;
+
+fn cache_with_history() -> Cached {
+    let mut cached = Cached::new().expect("couldnt build default cache");
+    for (id, data) in cached.data_mut().iter_mut() {
+        data.current_sales = (0..SALES_PER_TOKEN)
+            .map(|n| Sale {
+                order_hash: Some(format!("0x{:064x}", u64::from(*id as u16) * 100 + n)),
+                amount: (u64::from(*id as u16) * 1_000_000_000_000_000 + n).into(),
+                payment_token: PaymentToken::eth(),
+                created_timestamp: 1_650_000_000 + n * 3_600,
+                expiration_timestamp: Some(1_660_000_000 + n * 3_600),
+                sale_type: SaleType::BuyNow,
+                auction: None,
+                price_wei: (u64::from(*id as u16) * 1_000_000_000_000_000 + n).into(),
+                price_usd: Some(1_500.0 * n as f64),
+                platform: Marketplace::OpenSea,
+            })
+            .collect();
+    }
+    cached
+}
+
+fn bench_format(c: &mut Criterion, cached: &Cached, format: CacheFormat, name: &str) {
+    let path = env::temp_dir()
+        .join(format!("kong-scraper-bench-{}.cache", name))
+        .to_string_lossy()
+        .to_string();
+    let store = CacheStore::new(path, 0, false, format);
+    c.bench_function(&format!("save {}", name), |b| {
+        b.iter(|| store.save(cached).unwrap())
+    });
+    c.bench_function(&format!("load {}", name), |b| {
+        b.iter(|| store.restore().unwrap().unwrap())
+    });
+}
+
+fn cache_benches(c: &mut Criterion) {
+    let cached = cache_with_history();
+    bench_format(c, &cached, CacheFormat::Json, "json");
+    bench_format(c, &cached, CacheFormat::Binary, "binary");
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = cache_benches
+}
+criterion_main!(benches);
@@ -0,0 +1,89 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Response, Server,
+};
+use kong_scraper::opensea_client::{HttpOptions, ListingsRequest, ListingsResponse, OpenseaClient};
+use std::{convert::Infallible, fs, time::Duration};
+use tokio::runtime::Runtime;
+
+// Requests per iteration, roughly one update's worth of changed tokens.
+const REQUESTS: u64 = 200;
+const CONTRACT: &str = "0xEf0182dc0574cd5874494a120750FD222FdB909a";
+
+// Answers every request with the same listings payload.
+fn start_server(rt: &Runtime) -> String {
+    let body = fs::read_to_string("tests/fixtures/listings_basic.json").unwrap();
+    rt.block_on(async move {
+        let make_svc = make_service_fn(move |_| {
+            let body = body.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |_| {
+                    let body = body.clone();
+                    async move { Ok::<_, Infallible>(Response::new(Body::from(body))) }
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_svc);
+        let url = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+        url
+    })
+}
+
+fn bench_http(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let url = start_server(&rt);
+    let listings = format!("{}/api/v1/asset/{}/3/listings", url, CONTRACT);
+    let mut group = c.benchmark_group("listings requests");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(REQUESTS));
+
+    // What every request did before the client was shared.
+    group.bench_function("fresh client per request", |b| {
+        b.iter(|| {
+            rt.block_on(async {
+                for _ in 0..REQUESTS {
+                    let res = reqwest::Client::new().get(&listings).send().await.unwrap();
+                    res.json::<ListingsResponse>().await.unwrap();
+                }
+            })
+        })
+    });
+
+    let shared = HttpOptions::default().build().unwrap();
+    group.bench_function("shared client", |b| {
+        b.iter(|| {
+            rt.block_on(async {
+                for _ in 0..REQUESTS {
+                    let res = shared.get(&listings).send().await.unwrap();
+                    res.json::<ListingsResponse>().await.unwrap();
+                }
+            })
+        })
+    });
+
+    let os_client = OpenseaClient::new(
+        &[String::from("bench-key")],
+        &url,
+        Duration::ZERO,
+        Duration::ZERO,
+    );
+    let req = ListingsRequest::new(CONTRACT.to_string(), 3, None);
+    group.bench_function("OpenseaClient", |b| {
+        b.iter(|| {
+            rt.block_on(async {
+                for _ in 0..REQUESTS {
+                    os_client
+                        .request::<ListingsRequest, ListingsResponse>(&req)
+                        .await
+                        .unwrap();
+                }
+            })
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_http);
+criterion_main!(benches);
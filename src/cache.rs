@@ -0,0 +1,304 @@
+use crate::{
+    kong_data::Cached,
+    metrics::CACHE_SIZE,
+    payment::{parse_units, ETH_DECIMALS},
+};
+use anyhow::anyhow;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+    path::Path,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+use tracing::{error, info, warn};
+use web3::types::U256;
+
+// Version 1 is the legacy format: a bare `Cached` without an envelope.
+pub const CACHE_VERSION: u32 = 3;
+
+// MIGRATIONS[i] upgrades the `data` of a version i + 1 cache to version i + 2.
+const MIGRATIONS: &[fn(Value) -> anyhow::Result<Value>] = &[migrate_v1_to_v2, migrate_v2_to_v3];
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheFormat {
+    Json,
+    // Gzipped MessagePack: version (u32 LE), SHA-256 of the payload, payload.
+    Binary,
+}
+impl FromStr for CacheFormat {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(CacheFormat::Json),
+            "binary" => Ok(CacheFormat::Binary),
+            other => Err(anyhow!("Unknown cache format: {}", other)),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct CacheFile {
+    version: u32,
+    checksum: String,
+    data: Value,
+}
+
+pub struct CacheStore {
+    path: String,
+    backups: usize,
+    // Minimum age of the newest backup before another one is taken.
+    backup_interval: Duration,
+    fallback_to_backup: bool,
+    format: CacheFormat,
+}
+impl CacheStore {
+    pub fn new(
+        path: String,
+        backups: usize,
+        fallback_to_backup: bool,
+        format: CacheFormat,
+    ) -> Self {
+        CacheStore {
+            path,
+            backups,
+            backup_interval: Duration::ZERO,
+            fallback_to_backup,
+            format,
+        }
+    }
+
+    pub fn set_backup_interval(&mut self, interval: Duration) {
+        self.backup_interval = interval;
+    }
+
+    // Returns Ok(None) only when there is no cache at all. A cache that exists but
+    // can't be read is an error unless falling back to a backup was enabled.
+    pub fn restore(&self) -> anyhow::Result<Option<Cached>> {
+        if !Path::new(&self.path).exists() {
+            return Ok(None);
+        }
+        let err = match read_cache(&self.path) {
+            Ok(cached) => return Ok(Some(cached)),
+            Err(err) => err,
+        };
+        error!(path = %self.path, error = %err, "Cache is corrupt");
+        if !self.fallback_to_backup {
+            return Err(anyhow!(
+                "Cache at {} is corrupt: {}. Restore it from one of [{}] or remove it to start over",
+                &self.path,
+                err,
+                self.existing_backups().join(", ")
+            ));
+        }
+        for backup in self.existing_backups() {
+            match read_cache(&backup) {
+                Ok(cached) => {
+                    warn!(backup = %backup, "Restored cache from backup");
+                    return Ok(Some(cached));
+                }
+                Err(err) => warn!(backup = %backup, error = %err, "Backup is unusable"),
+            }
+        }
+        Err(anyhow!(
+            "Cache at {} is corrupt and no usable backup was found",
+            &self.path
+        ))
+    }
+
+    pub fn save(&self, cached: &Cached) -> anyhow::Result<()> {
+        let tmp_path = format!("{}.tmp", &self.path);
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        match self.format {
+            CacheFormat::Json => write_json(&mut writer, cached)?,
+            CacheFormat::Binary => write_binary(&mut writer, cached)?,
+        }
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        self.rotate_backups()?;
+        fs::rename(&tmp_path, &self.path)?;
+        CACHE_SIZE.set(fs::metadata(&self.path)?.len() as i64);
+        Ok(())
+    }
+
+    fn backup_path(&self, n: usize) -> String {
+        format!("{}.{}", &self.path, n)
+    }
+
+    fn existing_backups(&self) -> Vec<String> {
+        (1..=self.backups)
+            .map(|n| self.backup_path(n))
+            .filter(|p| Path::new(p).exists())
+            .collect()
+    }
+
+    // The cache is saved after every job, so rotating on each save would push a
+    // bad cache through every backup within a single cycle.
+    fn rotate_backups(&self) -> anyhow::Result<()> {
+        if self.backups == 0 || !Path::new(&self.path).exists() || !self.backup_due() {
+            return Ok(());
+        }
+        for n in (1..self.backups).rev() {
+            let from = self.backup_path(n);
+            if Path::new(&from).exists() {
+                fs::rename(&from, self.backup_path(n + 1))?;
+            }
+        }
+        // Copy rather than rename so there is always a cache at `path`.
+        fs::copy(&self.path, self.backup_path(1))?;
+        Ok(())
+    }
+
+    fn backup_due(&self) -> bool {
+        let taken = match fs::metadata(self.backup_path(1)).and_then(|m| m.modified()) {
+            Ok(taken) => taken,
+            Err(_) => return true,
+        };
+        match SystemTime::now().duration_since(taken) {
+            Ok(age) => age >= self.backup_interval,
+            // Taken in the future, so the clock moved back. Don't wait on it.
+            Err(_) => true,
+        }
+    }
+}
+
+// Reads a cache in either format and writes it back out in `format`.
+pub fn convert_cache(input: &str, output: &str, format: CacheFormat) -> anyhow::Result<()> {
+    let cached = read_cache(input)?;
+    CacheStore::new(output.to_string(), 0, false, format).save(&cached)
+}
+
+fn read_cache(path: &str) -> anyhow::Result<Cached> {
+    let mut reader = BufReader::new(File::open(path)?);
+    if reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        read_binary(reader)
+    } else {
+        read_json(reader)
+    }
+}
+
+fn write_json<W: Write>(writer: W, cached: &Cached) -> anyhow::Result<()> {
+    let data = serde_json::to_value(cached)?;
+    let file = CacheFile {
+        version: CACHE_VERSION,
+        checksum: hex::encode(Sha256::digest(serde_json::to_vec(&data)?)),
+        data,
+    };
+    serde_json::to_writer_pretty(writer, &file)?;
+    Ok(())
+}
+
+fn read_json<R: Read>(reader: R) -> anyhow::Result<Cached> {
+    let raw: Value = serde_json::from_reader(reader)?;
+    let (version, data) = match raw.get("version") {
+        Some(_) => {
+            let file: CacheFile = serde_json::from_value(raw)?;
+            verify_checksum(&serde_json::to_vec(&file.data)?, &file.checksum)?;
+            (file.version, file.data)
+        }
+        None => (1, raw),
+    };
+    Ok(serde_json::from_value(migrate(version, data)?)?)
+}
+
+fn write_binary<W: Write>(writer: W, cached: &Cached) -> anyhow::Result<()> {
+    let payload = rmp_serde::to_vec_named(cached)?;
+    let mut encoder = GzEncoder::new(writer, Compression::fast());
+    encoder.write_all(&CACHE_VERSION.to_le_bytes())?;
+    encoder.write_all(&Sha256::digest(&payload))?;
+    encoder.write_all(&payload)?;
+    encoder.finish()?;
+    Ok(())
+}
+
+fn read_binary<R: Read>(reader: R) -> anyhow::Result<Cached> {
+    let mut bytes = Vec::new();
+    GzDecoder::new(reader).read_to_end(&mut bytes)?;
+    if bytes.len() < 36 {
+        return Err(anyhow!("Binary cache is truncated"));
+    }
+    let (header, payload) = bytes.split_at(36);
+    let version = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+    verify_checksum(payload, &hex::encode(&header[4..]))?;
+    if version == CACHE_VERSION {
+        return Ok(rmp_serde::from_slice(payload)?);
+    }
+    // Older versions go through serde_json::Value so they share the JSON migrations.
+    let value: rmpv::Value = rmp_serde::from_slice(payload)?;
+    let data = serde_json::to_value(value)?;
+    Ok(serde_json::from_value(migrate(version, data)?)?)
+}
+
+fn verify_checksum(payload: &[u8], found: &str) -> anyhow::Result<()> {
+    let expected = hex::encode(Sha256::digest(payload));
+    if expected != found {
+        return Err(anyhow!(
+            "Checksum mismatch. Expected: {} Found: {}",
+            expected,
+            found
+        ));
+    }
+    Ok(())
+}
+
+fn migrate(version: u32, mut data: Value) -> anyhow::Result<Value> {
+    if version == 0 || version > CACHE_VERSION {
+        return Err(anyhow!("Unsupported cache version: {}", version));
+    }
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize - 1) {
+        info!(from = from + 1, to = from + 2, "Migrating cache");
+        data = migration(data)?;
+    }
+    Ok(data)
+}
+
+// Version 2 only added the envelope, the data itself is unchanged.
+fn migrate_v1_to_v2(data: Value) -> anyhow::Result<Value> {
+    Ok(data)
+}
+
+// Version 3 prices sales in exact wei instead of floating point ETH. Old prices
+// are taken as printed, which is as exact as they ever were. Sales from before
+// amounts were recorded were all in ETH, so the amount is the price.
+fn migrate_v2_to_v3(mut data: Value) -> anyhow::Result<Value> {
+    let tokens = data
+        .get_mut("data")
+        .and_then(Value::as_object_mut)
+        .ok_or_else(|| anyhow!("Cache has no token data"))?;
+    for token in tokens.values_mut() {
+        let sales = token
+            .get_mut("current_sales")
+            .and_then(Value::as_array_mut)
+            .ok_or_else(|| anyhow!("Token has no current sales"))?;
+        for sale in sales.iter_mut().filter_map(Value::as_object_mut) {
+            let price_eth = sale
+                .remove("price_eth")
+                .and_then(|p| p.as_f64())
+                .ok_or_else(|| anyhow!("Sale has no price"))?;
+            let price_wei = eth_to_wei(price_eth)?.to_string();
+            sale.entry("amount")
+                .or_insert_with(|| price_wei.clone().into());
+            sale.insert("price_wei".to_string(), price_wei.into());
+        }
+    }
+    Ok(data)
+}
+
+fn eth_to_wei(price_eth: f64) -> anyhow::Result<U256> {
+    let printed = price_eth.to_string();
+    // Digits past wei are float noise.
+    let truncated = match printed.split_once('.') {
+        Some((whole, frac)) if frac.len() > ETH_DECIMALS.into() => {
+            format!("{}.{}", whole, &frac[..ETH_DECIMALS.into()])
+        }
+        _ => printed,
+    };
+    parse_units(&truncated, ETH_DECIMALS)
+        .map_err(|err| anyhow!("Invalid price {}: {}", price_eth, err))
+}
@@ -0,0 +1,229 @@
+use crate::{
+    cache::CacheFormat,
+    opensea_client::{ApiVersion, PriceStrategy},
+    reader::ReadMode,
+};
+use std::{env, str::FromStr, time::Duration};
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub node_url: String,
+    // Tried in order when `node_url` fails. Only used for contract reads.
+    pub rpc_fallback_urls: Vec<String>,
+    pub os_keys: Vec<String>,
+    pub mongo_url: String,
+    pub cache_path: String,
+    pub cache_backups: usize,
+    pub cache_backup_interval: Duration,
+    pub cache_fallback_to_backup: bool,
+    pub cache_format: CacheFormat,
+    pub update_interval: Duration,
+    pub shutdown_timeout: Duration,
+    pub port: u16,
+    pub progress_interval: Duration,
+    pub staleness_threshold: Duration,
+    pub opensea_url: String,
+    pub opensea_api: ApiVersion,
+    pub opensea_collection: String,
+    pub price_strategy: PriceStrategy,
+    pub eth_usd_feed: String,
+    pub eth_usd_fixed: Option<f64>,
+    pub opensea_throttle: Duration,
+    pub opensea_backoff: Duration,
+    pub opensea_timeout: Duration,
+    pub opensea_connect_timeout: Duration,
+    pub opensea_proxy: Option<String>,
+    pub opensea_user_agent: String,
+    pub opensea_max_retries: u8,
+    pub opensea_breaker_threshold: u32,
+    pub opensea_breaker_cooldown: Duration,
+    pub opensea_key_cooldown: Duration,
+    pub opensea_record: Option<String>,
+    pub opensea_replay: Option<String>,
+    pub index_sales: bool,
+    pub sales_start_block: Option<u64>,
+    pub sales_log_range: u64,
+    pub confirmations: u64,
+    pub reorg_window: usize,
+    pub read_mode: ReadMode,
+    pub multicall_chunk_size: usize,
+    pub rpc_batch_size: usize,
+    pub rpc_max_retries: u8,
+    pub rpc_backoff: Duration,
+    pub rpc_cooldown: Duration,
+    pub refresh_metadata: bool,
+    pub metadata_interval: Duration,
+    pub ipfs_gateway: String,
+}
+impl Config {
+    // Defaults for everything that isn't a credential or endpoint. `os_keys` is a
+    // comma separated list of OpenSea API keys.
+    pub fn new(node_url: String, os_keys: String, mongo_url: String) -> Self {
+        Config {
+            node_url,
+            rpc_fallback_urls: Vec::new(),
+            os_keys: os_keys
+                .split(',')
+                .map(str::trim)
+                .filter(|k| !k.is_empty())
+                .map(String::from)
+                .collect(),
+            mongo_url,
+            cache_path: String::from("src/utils/cache.json"),
+            cache_backups: 3,
+            cache_backup_interval: Duration::from_secs(3600),
+            cache_fallback_to_backup: false,
+            cache_format: CacheFormat::Json,
+            update_interval: Duration::from_secs(300),
+            shutdown_timeout: Duration::from_secs(30),
+            port: 8080,
+            progress_interval: Duration::from_secs(30),
+            staleness_threshold: Duration::from_secs(1800),
+            opensea_url: String::from("https://api.opensea.io"),
+            opensea_api: ApiVersion::V1,
+            opensea_collection: String::from("rumble-kong-league"),
+            price_strategy: PriceStrategy::Incremental,
+            // Chainlink ETH/USD on mainnet.
+            eth_usd_feed: String::from("0x5f4eC3Df9cbd43714FE2740f5E3616155c5b8419"),
+            eth_usd_fixed: None,
+            opensea_throttle: Duration::from_millis(300),
+            opensea_backoff: Duration::from_secs(3),
+            opensea_timeout: Duration::from_secs(30),
+            opensea_connect_timeout: Duration::from_secs(10),
+            opensea_proxy: None,
+            opensea_user_agent: format!("kong-scraper/{}", env!("CARGO_PKG_VERSION")),
+            opensea_max_retries: 5,
+            opensea_breaker_threshold: 10,
+            opensea_breaker_cooldown: Duration::from_secs(60),
+            opensea_key_cooldown: Duration::from_secs(600),
+            opensea_record: None,
+            opensea_replay: None,
+            index_sales: false,
+            sales_start_block: None,
+            sales_log_range: 2_000,
+            confirmations: 12,
+            reorg_window: 64,
+            read_mode: ReadMode::Batch,
+            multicall_chunk_size: 500,
+            rpc_batch_size: 1_000,
+            rpc_max_retries: 3,
+            rpc_backoff: Duration::from_millis(500),
+            rpc_cooldown: Duration::from_secs(30),
+            refresh_metadata: false,
+            metadata_interval: Duration::from_secs(86_400),
+            ipfs_gateway: String::from("https://ipfs.io"),
+        }
+    }
+
+    pub fn from_env() -> anyhow::Result<Self> {
+        Config::load(env::var("OS_KEY")?, env::var("MONGO_URL")?)
+    }
+
+    // For subcommands that only read the chain. OpenSea and Mongo aren't used,
+    // so their credentials aren't required.
+    pub fn chain_from_env() -> anyhow::Result<Self> {
+        Config::load(
+            env::var("OS_KEY").unwrap_or_default(),
+            env::var("MONGO_URL").unwrap_or_default(),
+        )
+    }
+
+    fn load(os_keys: String, mongo_url: String) -> anyhow::Result<Self> {
+        let d = Config::new(env::var("INFURA_MAINNET")?, os_keys, mongo_url);
+        Ok(Config {
+            cache_path: env_or("CACHE_PATH", d.cache_path)?,
+            cache_backups: env_or("CACHE_BACKUPS", d.cache_backups)?,
+            cache_backup_interval: env_secs("CACHE_BACKUP_INTERVAL_SECS", d.cache_backup_interval)?,
+            cache_fallback_to_backup: env_or(
+                "CACHE_FALLBACK_TO_BACKUP",
+                d.cache_fallback_to_backup,
+            )?,
+            cache_format: env_or("CACHE_FORMAT", d.cache_format)?,
+            update_interval: env_secs("UPDATE_INTERVAL_SECS", d.update_interval)?,
+            shutdown_timeout: env_secs("SHUTDOWN_TIMEOUT_SECS", d.shutdown_timeout)?,
+            port: env_or("PORT", d.port)?,
+            progress_interval: env_secs("PROGRESS_INTERVAL_SECS", d.progress_interval)?,
+            staleness_threshold: env_secs("STALENESS_THRESHOLD_SECS", d.staleness_threshold)?,
+            opensea_url: env_or("OPENSEA_URL", d.opensea_url)?,
+            opensea_api: env_or("OPENSEA_API", d.opensea_api)?,
+            opensea_collection: env_or("OPENSEA_COLLECTION", d.opensea_collection)?,
+            price_strategy: env_or("PRICE_STRATEGY", d.price_strategy)?,
+            eth_usd_feed: env_or("ETH_USD_FEED", d.eth_usd_feed)?,
+            eth_usd_fixed: env::var("ETH_USD_FIXED")
+                .ok()
+                .map(|v| v.parse())
+                .transpose()?,
+            opensea_throttle: env_millis("OPENSEA_THROTTLE_MS", d.opensea_throttle)?,
+            opensea_backoff: env_millis("OPENSEA_BACKOFF_MS", d.opensea_backoff)?,
+            opensea_timeout: env_secs("OPENSEA_TIMEOUT_SECS", d.opensea_timeout)?,
+            opensea_connect_timeout: env_secs(
+                "OPENSEA_CONNECT_TIMEOUT_SECS",
+                d.opensea_connect_timeout,
+            )?,
+            opensea_proxy: env::var("OPENSEA_PROXY").ok(),
+            opensea_user_agent: env_or("OPENSEA_USER_AGENT", d.opensea_user_agent)?,
+            opensea_max_retries: env_or("OPENSEA_MAX_RETRIES", d.opensea_max_retries)?,
+            opensea_breaker_threshold: env_or(
+                "OPENSEA_BREAKER_THRESHOLD",
+                d.opensea_breaker_threshold,
+            )?,
+            opensea_breaker_cooldown: env_secs(
+                "OPENSEA_BREAKER_COOLDOWN_SECS",
+                d.opensea_breaker_cooldown,
+            )?,
+            opensea_key_cooldown: env_secs("OPENSEA_KEY_COOLDOWN_SECS", d.opensea_key_cooldown)?,
+            opensea_record: env::var("OPENSEA_RECORD").ok(),
+            opensea_replay: env::var("OPENSEA_REPLAY").ok(),
+            index_sales: env_or("INDEX_SALES", d.index_sales)?,
+            sales_start_block: env::var("SALES_START_BLOCK")
+                .ok()
+                .map(|v| v.parse())
+                .transpose()?,
+            sales_log_range: env_or("SALES_LOG_RANGE", d.sales_log_range)?,
+            confirmations: env_or("CONFIRMATIONS", d.confirmations)?,
+            reorg_window: env_or("REORG_WINDOW", d.reorg_window)?,
+            read_mode: env_or("READ_MODE", d.read_mode)?,
+            multicall_chunk_size: env_or("MULTICALL_CHUNK_SIZE", d.multicall_chunk_size)?,
+            rpc_fallback_urls: env::var("RPC_FALLBACK_URLS")
+                .map(|urls| {
+                    urls.split(',')
+                        .map(str::trim)
+                        .filter(|u| !u.is_empty())
+                        .map(String::from)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            rpc_batch_size: env_or("RPC_BATCH_SIZE", d.rpc_batch_size)?,
+            rpc_max_retries: env_or("RPC_MAX_RETRIES", d.rpc_max_retries)?,
+            rpc_backoff: env_millis("RPC_BACKOFF_MS", d.rpc_backoff)?,
+            rpc_cooldown: env_secs("RPC_COOLDOWN_SECS", d.rpc_cooldown)?,
+            refresh_metadata: env_or("REFRESH_METADATA", d.refresh_metadata)?,
+            metadata_interval: env_secs("METADATA_INTERVAL_SECS", d.metadata_interval)?,
+            ipfs_gateway: env_or("IPFS_GATEWAY", d.ipfs_gateway)?,
+            ..d
+        })
+    }
+}
+
+pub fn env_or<T: FromStr>(key: &str, default: T) -> anyhow::Result<T>
+where
+    T::Err: std::fmt::Display,
+{
+    match env::var(key) {
+        Ok(val) => val
+            .parse::<T>()
+            .map_err(|err| anyhow::anyhow!("Invalid value for {}: {}", key, err)),
+        Err(_) => Ok(default),
+    }
+}
+
+fn env_secs(key: &str, default: Duration) -> anyhow::Result<Duration> {
+    Ok(Duration::from_secs(env_or(key, default.as_secs())?))
+}
+
+fn env_millis(key: &str, default: Duration) -> anyhow::Result<Duration> {
+    Ok(Duration::from_millis(env_or(
+        key,
+        default.as_millis() as u64,
+    )?))
+}
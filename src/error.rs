@@ -0,0 +1,123 @@
+use thiserror::Error;
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+const SNIPPET_LEN: usize = 200;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Transport error: {0}")]
+    Transport(#[source] BoxError),
+    #[error("Request timed out")]
+    Timeout,
+    #[error("Circuit open after {failures} consecutive failures")]
+    CircuitOpen { failures: u32 },
+    #[error("Rate limited after {attempts} attempts")]
+    RateLimited { attempts: u8 },
+    #[error("Rejected with {status}. Check the API key")]
+    Unauthorized { status: u16 },
+    #[error("Unexpected response. Code: {status} Body: {body}")]
+    HttpStatus { status: u16, body: String },
+    #[error("Couldnt decode response: {source}. Payload: {snippet}")]
+    Decode {
+        #[source]
+        source: serde_json::Error,
+        snippet: String,
+    },
+    #[error("RPC error: {0}")]
+    Rpc(#[from] web3::Error),
+    #[error("ABI error: {0}")]
+    Abi(#[from] ethabi::Error),
+    #[error("Storage error: {0}")]
+    Storage(#[source] BoxError),
+    #[error("Invalid token metadata: {0}")]
+    Metadata(String),
+    #[error("Block {0} hasnt been mined yet")]
+    UnknownBlock(u64),
+    #[error("No price history reaches back to {0}")]
+    NoPriceHistory(u64),
+    #[error("Reorg reaches past the last {window} recorded blocks. Reindex from an earlier block")]
+    ReorgTooDeep { window: usize },
+}
+impl Error {
+    pub fn decode(source: serde_json::Error, payload: &str) -> Self {
+        Error::Decode {
+            source,
+            snippet: payload.chars().take(SNIPPET_LEN).collect(),
+        }
+    }
+    pub fn http_status(status: u16, body: &str) -> Self {
+        Error::HttpStatus {
+            status,
+            body: body.chars().take(SNIPPET_LEN).collect(),
+        }
+    }
+    pub fn storage<E: Into<BoxError>>(err: E) -> Self {
+        Error::Storage(err.into())
+    }
+
+    // Stable label for metrics and alerts.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Transport(_) => "transport",
+            Error::Timeout => "timeout",
+            Error::CircuitOpen { .. } => "circuit_open",
+            Error::Unauthorized { .. } => "unauthorized",
+            Error::RateLimited { .. } => "rate_limited",
+            Error::HttpStatus { .. } => "http_status",
+            Error::Decode { .. } => "decode",
+            Error::Rpc(_) => "rpc",
+            Error::Abi(_) => "abi",
+            Error::Storage(_) => "storage",
+            Error::Metadata(_) => "metadata",
+            Error::UnknownBlock(_) => "unknown_block",
+            Error::NoPriceHistory(_) => "no_price_history",
+            Error::ReorgTooDeep { .. } => "reorg",
+        }
+    }
+
+    // Whether trying the same operation again later could succeed.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Transport(_)
+            | Error::Timeout
+            | Error::CircuitOpen { .. }
+            | Error::RateLimited { .. }
+            | Error::Rpc(_) => true,
+            Error::HttpStatus { status, .. } => *status >= 500,
+            Error::Unauthorized { .. }
+            | Error::Decode { .. }
+            | Error::Abi(_)
+            | Error::Storage(_)
+            | Error::Metadata(_)
+            | Error::UnknownBlock(_)
+            | Error::NoPriceHistory(_)
+            | Error::ReorgTooDeep { .. } => false,
+        }
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, Error::HttpStatus { status: 404, .. })
+    }
+}
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            Error::Timeout
+        } else {
+            Error::Transport(err.into())
+        }
+    }
+}
+impl From<mongodb::error::Error> for Error {
+    fn from(err: mongodb::error::Error) -> Self {
+        Error::storage(err)
+    }
+}
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::storage(err)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,170 @@
+use crate::{opensea_client::OpenseaClient, utils::get_current_ts};
+use mongodb::{bson::doc, Client};
+use serde::Serialize;
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio::time;
+use web3::{transports::Http, Web3};
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+// Pinging OpenSea spends API quota, so readiness reuses a recent result.
+const OPENSEA_PROBE_TTL: Duration = Duration::from_secs(60);
+
+// Last successful update times, shared between the scraper and the server.
+#[derive(Debug, Default)]
+pub struct SyncStatus {
+    prev_sales_ts: AtomicU64,
+    prev_names_ts: AtomicU64,
+}
+impl SyncStatus {
+    pub fn set_prev_sales_ts(&self, ts: u64) {
+        self.prev_sales_ts.store(ts, Ordering::Relaxed)
+    }
+    pub fn set_prev_names_ts(&self, ts: u64) {
+        self.prev_names_ts.store(ts, Ordering::Relaxed)
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Check {
+    pub ok: bool,
+    pub error: Option<String>,
+}
+#[derive(Serialize, Debug)]
+pub struct Freshness {
+    pub last_update: u64,
+    pub age_secs: u64,
+    pub stale: bool,
+}
+// Only how recent the last updates are. Cheap enough for a liveness probe.
+#[derive(Serialize, Debug)]
+pub struct LivenessReport {
+    pub sales: Freshness,
+    pub names: Freshness,
+}
+impl LivenessReport {
+    pub fn is_fresh(&self) -> bool {
+        !self.sales.stale && !self.names.stale
+    }
+}
+#[derive(Serialize, Debug)]
+pub struct HealthReport {
+    pub mongo: Check,
+    pub rpc: Check,
+    pub opensea: Check,
+    pub sales: Freshness,
+    pub names: Freshness,
+}
+impl HealthReport {
+    pub fn is_fresh(&self) -> bool {
+        !self.sales.stale && !self.names.stale
+    }
+    pub fn is_ready(&self) -> bool {
+        self.is_fresh() && self.mongo.ok && self.rpc.ok && self.opensea.ok
+    }
+}
+
+pub struct Health {
+    mongo: Client,
+    web3: Web3<Http>,
+    os_client: OpenseaClient,
+    status: Arc<SyncStatus>,
+    staleness_threshold: Duration,
+    started_at: u64,
+    opensea_check: Mutex<Option<(Instant, Check)>>,
+}
+impl Health {
+    pub fn new(
+        mongo: Client,
+        web3: Web3<Http>,
+        os_client: OpenseaClient,
+        status: Arc<SyncStatus>,
+        staleness_threshold: Duration,
+    ) -> Self {
+        Health {
+            mongo,
+            web3,
+            os_client,
+            status,
+            staleness_threshold,
+            started_at: get_current_ts(),
+            opensea_check: Mutex::new(None),
+        }
+    }
+
+    pub fn liveness(&self) -> LivenessReport {
+        LivenessReport {
+            sales: self.freshness(self.status.prev_sales_ts.load(Ordering::Relaxed)),
+            names: self.freshness(self.status.prev_names_ts.load(Ordering::Relaxed)),
+        }
+    }
+
+    // Probes Mongo, the node and OpenSea on top of the liveness checks.
+    pub async fn report(&self) -> HealthReport {
+        let (mongo, rpc, opensea) = tokio::join!(
+            probe(async {
+                self.mongo
+                    .database("admin")
+                    .run_command(doc! { "ping": 1 }, None)
+                    .await?;
+                Ok(())
+            }),
+            probe(async {
+                self.web3.eth().block_number().await?;
+                Ok(())
+            }),
+            self.opensea(),
+        );
+        HealthReport {
+            mongo,
+            rpc,
+            opensea,
+            sales: self.freshness(self.status.prev_sales_ts.load(Ordering::Relaxed)),
+            names: self.freshness(self.status.prev_names_ts.load(Ordering::Relaxed)),
+        }
+    }
+
+    async fn opensea(&self) -> Check {
+        if let Some((at, check)) = &*self.opensea_check.lock().unwrap() {
+            if at.elapsed() < OPENSEA_PROBE_TTL {
+                return check.clone();
+            }
+        }
+        let check = probe(self.os_client.ping()).await;
+        *self.opensea_check.lock().unwrap() = Some((Instant::now(), check.clone()));
+        check
+    }
+
+    // Nothing counts as stale until the threshold has passed since startup.
+    fn freshness(&self, last_update: u64) -> Freshness {
+        let age_secs = get_current_ts().saturating_sub(last_update.max(self.started_at));
+        Freshness {
+            last_update,
+            age_secs,
+            stale: age_secs > self.staleness_threshold.as_secs(),
+        }
+    }
+}
+
+async fn probe<F: Future<Output = anyhow::Result<()>>>(check: F) -> Check {
+    match time::timeout(PROBE_TIMEOUT, check).await {
+        Ok(Ok(())) => Check {
+            ok: true,
+            error: None,
+        },
+        Ok(Err(err)) => Check {
+            ok: false,
+            error: Some(err.to_string()),
+        },
+        Err(_) => Check {
+            ok: false,
+            error: Some(String::from("Timed out")),
+        },
+    }
+}
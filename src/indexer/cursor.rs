@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use web3::types::H256;
+
+#[derive(Debug, Clone, Copy)]
+pub struct CursorOptions {
+    // Blocks that must be built on top of a block before its data is final.
+    pub confirmations: u64,
+    // Recent block hashes to keep. Reorgs deeper than this can't be rolled back.
+    pub window: usize,
+    // Block to start indexing from. Defaults to the head on the first poll.
+    pub start_block: Option<u64>,
+    // Most blocks one log query may span. Providers reject wider ranges.
+    pub log_range: u64,
+}
+impl Default for CursorOptions {
+    fn default() -> Self {
+        CursorOptions {
+            confirmations: 12,
+            window: 64,
+            start_block: None,
+            log_range: 2_000,
+        }
+    }
+}
+
+// Position of a log indexer, along with the hashes of the blocks it has indexed
+// most recently so that a reorg shows up as one of them changing.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct BlockCursor {
+    next_block: Option<u64>,
+    hashes: BTreeMap<u64, H256>,
+}
+impl BlockCursor {
+    // First block that hasn't been indexed, if indexing has started.
+    pub fn next_block(&self) -> Option<u64> {
+        self.next_block
+    }
+    // Recorded blocks, newest first. A reorg is found by walking these back
+    // until one still matches the chain.
+    pub fn recent(&self) -> impl Iterator<Item = (u64, H256)> + '_ {
+        self.hashes.iter().rev().map(|(n, h)| (*n, *h))
+    }
+    pub fn hash(&self, number: u64) -> Option<H256> {
+        self.hashes.get(&number).copied()
+    }
+    // Marks everything up to `to` as indexed, with the hashes seen for it.
+    pub fn advance(&mut self, to: u64, hashes: BTreeMap<u64, H256>, window: usize) {
+        self.hashes.extend(hashes);
+        self.next_block = Some(to + 1);
+        while self.hashes.len() > window {
+            self.hashes.pop_first();
+        }
+    }
+    // Forgets `from` and everything after it so it gets indexed again.
+    pub fn rewind(&mut self, from: u64) {
+        self.hashes.split_off(&from);
+        self.next_block = Some(from);
+    }
+}
+
+// Anything derived from a block, so it can be dropped when the block is.
+pub trait BlockItem {
+    fn block_number(&self) -> u64;
+}
+
+// Data indexed from logs, split into what could still be reorged away and
+// what is buried deep enough to be final.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Ledger<T> {
+    pub cursor: BlockCursor,
+    // Highest block whose items have been finalized.
+    final_through: Option<u64>,
+    pending: Vec<T>,
+    finalized: Vec<T>,
+}
+impl<T> Default for Ledger<T> {
+    fn default() -> Self {
+        Ledger {
+            cursor: BlockCursor::default(),
+            final_through: None,
+            pending: Vec::new(),
+            finalized: Vec::new(),
+        }
+    }
+}
+impl<T: BlockItem> Ledger<T> {
+    pub fn final_through(&self) -> Option<u64> {
+        self.final_through
+    }
+    pub fn pending(&self) -> &[T] {
+        &self.pending
+    }
+    pub fn finalized(&self) -> &[T] {
+        &self.finalized
+    }
+    pub fn finalized_mut(&mut self) -> &mut [T] {
+        &mut self.finalized
+    }
+    // Final items that haven't been handed off yet. They aren't kept after this.
+    pub fn take_finalized(&mut self) -> Vec<T> {
+        std::mem::take(&mut self.finalized)
+    }
+    pub fn extend(&mut self, items: Vec<T>) {
+        self.pending.extend(items);
+    }
+    // Drops everything from block `from` on, including final items that
+    // haven't been handed off. Returns how many items went.
+    pub fn rollback(&mut self, from: u64) -> usize {
+        self.cursor.rewind(from);
+        let before = self.pending.len() + self.finalized.len();
+        self.pending.retain(|item| item.block_number() < from);
+        self.finalized.retain(|item| item.block_number() < from);
+        if matches!(self.final_through, Some(through) if through >= from) {
+            self.final_through = from.checked_sub(1);
+        }
+        before - self.pending.len() - self.finalized.len()
+    }
+    // Moves pending items up to and including block `through` to final.
+    pub fn finalize(&mut self, through: u64) -> usize {
+        let (done, pending): (Vec<T>, Vec<T>) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|item| item.block_number() <= through);
+        self.pending = pending;
+        self.final_through = Some(self.final_through.map_or(through, |t| t.max(through)));
+        let count = done.len();
+        self.finalized.extend(done);
+        count
+    }
+}
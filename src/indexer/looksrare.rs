@@ -0,0 +1,67 @@
+use crate::{
+    error::Result,
+    indexer::{address, hash, param, parse_log, payment_token, token_id, uint, Fill, LogDecoder},
+    kong_data::Marketplace,
+};
+use std::fs::File;
+use web3::types::{Address, Log, H256};
+
+const EXCHANGE: &str = "0x59728544B08AB483533076417FbBB2fD0B17CE3a";
+
+pub struct LooksRare {
+    // A bid being accepted: the taker sells to the maker.
+    taker_ask: ethabi::Event,
+    // A listing being bought: the taker buys from the maker.
+    taker_bid: ethabi::Event,
+    exchange: Address,
+}
+impl LooksRare {
+    pub fn new() -> anyhow::Result<Self> {
+        let abi = ethabi::Contract::load(File::open("src/utils/looksrare_abi.json")?)?;
+        Ok(LooksRare {
+            taker_ask: abi.event("TakerAsk")?.clone(),
+            taker_bid: abi.event("TakerBid")?.clone(),
+            exchange: EXCHANGE.parse()?,
+        })
+    }
+}
+impl LogDecoder for LooksRare {
+    fn addresses(&self) -> Vec<Address> {
+        vec![self.exchange]
+    }
+    fn topics(&self) -> Vec<H256> {
+        vec![self.taker_ask.signature(), self.taker_bid.signature()]
+    }
+    fn decode(&self, log: &Log, collection: Address) -> Result<Vec<Fill>> {
+        let taker_sells = log.topics.first() == Some(&self.taker_ask.signature());
+        let event = if taker_sells {
+            &self.taker_ask
+        } else {
+            &self.taker_bid
+        };
+        let params = parse_log(event, log)?;
+        if address(param(&params, "collection")?)? != collection {
+            return Ok(Vec::new());
+        }
+        let token_id = match token_id(uint(param(&params, "tokenId")?)?) {
+            Some(id) => id,
+            None => return Ok(Vec::new()),
+        };
+        let taker = address(param(&params, "taker")?)?;
+        let maker = address(param(&params, "maker")?)?;
+        let (seller, buyer) = if taker_sells {
+            (taker, maker)
+        } else {
+            (maker, taker)
+        };
+        Ok(vec![Fill {
+            token_id,
+            marketplace: Marketplace::LooksRare,
+            order_hash: Some(hash(param(&params, "orderHash")?)?),
+            seller,
+            buyer,
+            amount: uint(param(&params, "price")?)?,
+            payment_token: payment_token(address(param(&params, "currency")?)?),
+        }])
+    }
+}
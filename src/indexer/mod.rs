@@ -0,0 +1,417 @@
+mod cursor;
+mod looksrare;
+mod seaport;
+mod x2y2;
+
+pub use cursor::{BlockCursor, BlockItem, CursorOptions, Ledger};
+pub use looksrare::LooksRare;
+pub use seaport::Seaport;
+pub use x2y2::X2Y2;
+
+use crate::{
+    config::Config,
+    error::{Error, Result},
+    kong_data::Marketplace,
+    metrics::{CHAIN_REORGS, INDEXED_BLOCK},
+    payment::{self, PaymentToken},
+    utils::get_contract_address,
+};
+use ethabi::{LogParam, RawLog, Token};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use tracing::{info, instrument, warn};
+use web3::{
+    transports::Http,
+    types::{Address, BlockId, BlockNumber, FilterBuilder, Log, H256, U256},
+    Web3,
+};
+
+// Blocks whose timestamps are read at once when they aren't already known.
+const BLOCK_CONCURRENCY: usize = 16;
+
+// What the indexer needs from a block header.
+#[derive(Debug, Clone, Copy)]
+struct Header {
+    hash: H256,
+    timestamp: u64,
+}
+
+// One token changing hands, read from a marketplace contract's logs.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct SaleRecord {
+    pub token_id: i16,
+    pub marketplace: Marketplace,
+    pub order_hash: Option<String>,
+    pub seller: Address,
+    pub buyer: Address,
+    // What the buyer paid for this token, in the smallest unit of `payment_token`.
+    #[serde(with = "payment::decimal")]
+    pub amount: U256,
+    pub payment_token: PaymentToken,
+    pub block_number: u64,
+    pub block_hash: H256,
+    pub timestamp: u64,
+    pub tx_hash: H256,
+    pub log_index: u64,
+    // USD value at the time of the sale. Filled in once the sale is final.
+    #[serde(default)]
+    pub price_usd: Option<f64>,
+}
+impl BlockItem for SaleRecord {
+    fn block_number(&self) -> u64 {
+        self.block_number
+    }
+}
+
+pub type SaleLedger = Ledger<SaleRecord>;
+
+// What one poll did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Poll {
+    pub head: u64,
+    // First block that was reorged out, if any.
+    pub reorg_from: Option<u64>,
+    pub rolled_back: usize,
+    pub indexed: usize,
+    pub finalized: usize,
+}
+
+// A sale decoded from a log, before the block it happened in is known.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fill {
+    pub token_id: i16,
+    pub marketplace: Marketplace,
+    pub order_hash: Option<String>,
+    pub seller: Address,
+    pub buyer: Address,
+    pub amount: U256,
+    pub payment_token: PaymentToken,
+}
+
+// Reads sales of one collection out of a marketplace's event logs.
+pub trait LogDecoder: Send + Sync {
+    // Contracts that emit the events.
+    fn addresses(&self) -> Vec<Address>;
+    // topic0 of every event the decoder understands.
+    fn topics(&self) -> Vec<H256>;
+    // Sales of `collection` in `log`. Logs for other collections decode to nothing.
+    fn decode(&self, log: &Log, collection: Address) -> Result<Vec<Fill>>;
+}
+
+// Indexes sales of a collection across every marketplace it has a decoder for.
+pub struct SaleIndexer {
+    web3: Web3<Http>,
+    collection: Address,
+    decoders: Vec<Box<dyn LogDecoder>>,
+    options: CursorOptions,
+}
+impl SaleIndexer {
+    pub fn new(node_url: &str, collection: &str) -> anyhow::Result<Self> {
+        Ok(SaleIndexer {
+            web3: Web3::new(Http::new(node_url)?),
+            collection: collection
+                .parse()
+                .map_err(|err| anyhow::anyhow!("Invalid address {}: {}", collection, err))?,
+            decoders: vec![
+                Box::new(Seaport::new()?),
+                Box::new(LooksRare::new()?),
+                Box::new(X2Y2::new()?),
+            ],
+            options: CursorOptions::default(),
+        })
+    }
+
+    pub fn set_cursor_options(&mut self, options: CursorOptions) {
+        self.options = options;
+    }
+
+    // Brings `ledger` up to the chain head. Blocks that were reorged out since
+    // the last poll are rolled back and indexed again from the new chain, and
+    // sales with enough confirmations are moved to final.
+    #[instrument(skip_all)]
+    pub async fn poll(&self, ledger: &mut SaleLedger) -> Result<Poll> {
+        let head = self.web3.eth().block_number().await?.as_u64();
+        let mut poll = Poll {
+            head,
+            ..Poll::default()
+        };
+        if let Some(from) = self.find_reorg(&ledger.cursor).await? {
+            if matches!(ledger.final_through(), Some(through) if through >= from) {
+                warn!(
+                    from,
+                    "Reorg reached final blocks. Raise the confirmation depth"
+                );
+            }
+            poll.reorg_from = Some(from);
+            poll.rolled_back = ledger.rollback(from);
+            CHAIN_REORGS.inc();
+            warn!(
+                from,
+                rolled_back = poll.rolled_back,
+                "Chain reorg. Rolled back"
+            );
+        }
+        let mut from = match (ledger.cursor.next_block(), self.options.start_block) {
+            (Some(next), _) | (None, Some(next)) => next,
+            (None, None) => head,
+        };
+        let window_start = head.saturating_sub((self.options.window as u64).saturating_sub(1));
+        // Catching up goes one log range at a time, and the cursor moves after
+        // each so a failure part way doesn't lose the ranges already read.
+        while from <= head {
+            let to = self.range_end(from, head);
+            // Headers are read before the logs, so a block replaced in between
+            // shows up as a log from a block with a different hash.
+            let mut headers = BTreeMap::new();
+            for number in from.max(window_start)..=to {
+                match header(&self.web3, number).await? {
+                    Some(header) => headers.insert(number, header),
+                    None => return Ok(poll),
+                };
+            }
+            let sales = self.sales_in(from, to, &headers).await?;
+            let moved = sales
+                .iter()
+                .any(|s| matches!(headers.get(&s.block_number), Some(h) if h.hash != s.block_hash));
+            if moved {
+                warn!("Chain changed while indexing. Retrying next poll");
+                return Ok(poll);
+            }
+            poll.indexed += sales.len();
+            ledger.extend(sales);
+            let hashes = headers.iter().map(|(n, h)| (*n, h.hash)).collect();
+            ledger.cursor.advance(to, hashes, self.options.window);
+            from = to + 1;
+        }
+        let final_through = head.saturating_sub(self.options.confirmations);
+        poll.finalized = ledger.finalize(final_through);
+        INDEXED_BLOCK
+            .with_label_values(&["latest"])
+            .set(head as i64);
+        INDEXED_BLOCK
+            .with_label_values(&["final"])
+            .set(final_through as i64);
+        Ok(poll)
+    }
+
+    // First recorded block whose hash no longer matches the chain. Walks back
+    // from the newest, since a block matching means everything before it does.
+    async fn find_reorg(&self, cursor: &BlockCursor) -> Result<Option<u64>> {
+        let mut reorg_from = None;
+        let mut checked = 0;
+        for (number, recorded) in cursor.recent() {
+            if header(&self.web3, number).await?.map(|h| h.hash) == Some(recorded) {
+                return Ok(reorg_from);
+            }
+            reorg_from = Some(number);
+            checked += 1;
+        }
+        match reorg_from {
+            Some(_) => Err(Error::ReorgTooDeep { window: checked }),
+            None => Ok(None),
+        }
+    }
+
+    // Last block of the log range starting at `from`.
+    fn range_end(&self, from: u64, to: u64) -> u64 {
+        to.min(from.saturating_add(self.options.log_range.max(1) - 1))
+    }
+
+    // Every sale in blocks `from_block` to `to_block` inclusive, oldest first.
+    #[instrument(skip(self))]
+    pub async fn sales(&self, from_block: u64, to_block: u64) -> Result<Vec<SaleRecord>> {
+        let mut records = Vec::new();
+        let mut from = from_block;
+        while from <= to_block {
+            let to = self.range_end(from, to_block);
+            records.extend(self.sales_in(from, to, &BTreeMap::new()).await?);
+            from = to + 1;
+        }
+        Ok(records)
+    }
+
+    // Sales in one log range. Timestamps come from `headers` where the block
+    // has already been read.
+    async fn sales_in(
+        &self,
+        from_block: u64,
+        to_block: u64,
+        headers: &BTreeMap<u64, Header>,
+    ) -> Result<Vec<SaleRecord>> {
+        let filter = FilterBuilder::default()
+            .from_block(BlockNumber::Number(from_block.into()))
+            .to_block(BlockNumber::Number(to_block.into()))
+            .address(self.decoders.iter().flat_map(|d| d.addresses()).collect())
+            .topics(
+                Some(self.decoders.iter().flat_map(|d| d.topics()).collect()),
+                None,
+                None,
+                None,
+            )
+            .build();
+        let logs = self.web3.eth().logs(filter).await?;
+        let mut records = Vec::new();
+        for log in &logs {
+            for fill in self.decode(log) {
+                records.push(SaleRecord {
+                    token_id: fill.token_id,
+                    marketplace: fill.marketplace,
+                    order_hash: fill.order_hash,
+                    seller: fill.seller,
+                    buyer: fill.buyer,
+                    amount: fill.amount,
+                    payment_token: fill.payment_token,
+                    block_number: log.block_number.unwrap_or_default().as_u64(),
+                    block_hash: log.block_hash.unwrap_or_default(),
+                    timestamp: 0,
+                    tx_hash: log.transaction_hash.unwrap_or_default(),
+                    log_index: log.log_index.unwrap_or_default().as_u64(),
+                    price_usd: None,
+                });
+            }
+        }
+        let timestamps = self
+            .timestamps(records.iter().map(|r| r.block_number).collect(), headers)
+            .await?;
+        for record in records.iter_mut() {
+            record.timestamp = timestamps[&record.block_number];
+        }
+        records.sort_by_key(|r| (r.block_number, r.log_index));
+        info!(
+            from_block,
+            to_block,
+            logs = logs.len(),
+            sales = records.len(),
+            "Indexed sales"
+        );
+        Ok(records)
+    }
+
+    // Sales in one log. Logs that don't decode are skipped so one odd event
+    // doesn't hold up the rest.
+    pub fn decode(&self, log: &Log) -> Vec<Fill> {
+        if log.is_removed() {
+            return Vec::new();
+        }
+        let topic = match log.topics.first() {
+            Some(topic) => topic,
+            None => return Vec::new(),
+        };
+        let decoder = self
+            .decoders
+            .iter()
+            .find(|d| d.addresses().contains(&log.address) && d.topics().contains(topic));
+        match decoder.map(|d| d.decode(log, self.collection)) {
+            Some(Ok(fills)) => fills,
+            Some(Err(err)) => {
+                warn!(tx_hash = ?log.transaction_hash, error = %err, "Couldnt decode sale log. Skipping it");
+                Vec::new()
+            }
+            None => Vec::new(),
+        }
+    }
+
+    async fn timestamps(
+        &self,
+        blocks: BTreeSet<u64>,
+        headers: &BTreeMap<u64, Header>,
+    ) -> Result<HashMap<u64, u64>> {
+        let (known, missing): (Vec<u64>, Vec<u64>) =
+            blocks.into_iter().partition(|n| headers.contains_key(n));
+        let mut timestamps: HashMap<u64, u64> =
+            known.iter().map(|n| (*n, headers[n].timestamp)).collect();
+        for numbers in missing.chunks(BLOCK_CONCURRENCY) {
+            let tasks: Vec<_> = numbers
+                .iter()
+                .map(|number| {
+                    let (web3, number) = (self.web3.clone(), *number);
+                    tokio::spawn(async move { (number, header(&web3, number).await) })
+                })
+                .collect();
+            for task in tasks {
+                let (number, header) = task.await.map_err(|err| Error::Transport(err.into()))?;
+                timestamps.insert(number, header?.map(|h| h.timestamp).unwrap_or_default());
+            }
+        }
+        Ok(timestamps)
+    }
+}
+
+async fn header(web3: &Web3<Http>, number: u64) -> Result<Option<Header>> {
+    let block = web3
+        .eth()
+        .block(BlockId::Number(BlockNumber::Number(number.into())))
+        .await?;
+    Ok(block.and_then(|b| {
+        b.hash.map(|hash| Header {
+            hash,
+            timestamp: b.timestamp.as_u64(),
+        })
+    }))
+}
+
+fn parse_log(event: &ethabi::Event, log: &Log) -> Result<Vec<LogParam>> {
+    Ok(event
+        .parse_log(RawLog {
+            topics: log.topics.clone(),
+            data: log.data.0.clone(),
+        })?
+        .params)
+}
+
+fn param(params: &[LogParam], name: &str) -> Result<Token> {
+    params
+        .iter()
+        .find(|p| p.name == name)
+        .map(|p| p.value.clone())
+        .ok_or_else(|| ethabi::Error::InvalidName(name.to_string()).into())
+}
+
+// Token ids outside the collection's range can't be Kongs.
+fn token_id(id: U256) -> Option<i16> {
+    if id > U256::from(i16::MAX) {
+        return None;
+    }
+    Some(id.low_u32() as i16)
+}
+
+// An indexer for the Kong contract with the configured confirmations and ranges.
+pub fn from_config(config: &Config) -> anyhow::Result<SaleIndexer> {
+    let mut indexer = SaleIndexer::new(&config.node_url, &get_contract_address())?;
+    indexer.set_cursor_options(CursorOptions {
+        confirmations: config.confirmations,
+        window: config.reorg_window,
+        start_block: config.sales_start_block,
+        log_range: config.sales_log_range,
+    });
+    Ok(indexer)
+}
+
+fn payment_token(address: Address) -> PaymentToken {
+    PaymentToken::resolve(&format!("{:?}", address))
+}
+
+fn invalid() -> crate::error::Error {
+    ethabi::Error::InvalidData.into()
+}
+fn address(token: Token) -> Result<Address> {
+    token.into_address().ok_or_else(invalid)
+}
+fn uint(token: Token) -> Result<U256> {
+    token.into_uint().ok_or_else(invalid)
+}
+fn tuple(token: Token) -> Result<Vec<Token>> {
+    match token {
+        Token::Tuple(fields) => Ok(fields),
+        _ => Err(invalid()),
+    }
+}
+fn array(token: Token) -> Result<Vec<Token>> {
+    token.into_array().ok_or_else(invalid)
+}
+fn hash(token: Token) -> Result<String> {
+    token
+        .into_fixed_bytes()
+        .map(|b| format!("0x{}", hex::encode(b)))
+        .ok_or_else(invalid)
+}
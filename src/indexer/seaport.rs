@@ -0,0 +1,150 @@
+use crate::{
+    error::Result,
+    indexer::{
+        address, array, hash, param, parse_log, payment_token, token_id, tuple, uint, Fill,
+        LogDecoder,
+    },
+    kong_data::Marketplace,
+};
+use ethabi::Token;
+use std::fs::File;
+use web3::types::{Address, Log, H256, U256};
+
+// Seaport 1.1, 1.4 and 1.5. OrderFulfilled is the same in all of them.
+const EXCHANGES: &[&str] = &[
+    "0x00000000006c3852cbEf3e08E8dF289169EdE581",
+    "0x00000000000001ad428e4906aE43D8F9852d0dD6",
+    "0x00000000000000ADc04C56Bf30aC9d3c0aAF14dC",
+];
+// Orders paying either of these were placed through OpenSea.
+const OPENSEA_FEE_RECIPIENTS: &[&str] = &[
+    "0x0000a26b00c1f0df003000390027140000faa719",
+    "0x8De9C5A032463C561423387a9648c5C7BCC5BC90",
+];
+
+// Seaport item types. Criteria items are resolved to plain ones in events.
+const NATIVE: u8 = 0;
+const ERC20: u8 = 1;
+const ERC721: u8 = 2;
+const ERC721_WITH_CRITERIA: u8 = 4;
+
+struct Item {
+    item_type: u8,
+    token: Address,
+    identifier: U256,
+    amount: U256,
+    recipient: Option<Address>,
+}
+impl Item {
+    fn from_token(token: Token) -> Result<Self> {
+        let mut fields = tuple(token)?.into_iter();
+        let mut next = || fields.next().ok_or_else(super::invalid);
+        Ok(Item {
+            item_type: uint(next()?)?.low_u32() as u8,
+            token: address(next()?)?,
+            identifier: uint(next()?)?,
+            amount: uint(next()?)?,
+            recipient: next().ok().map(address).transpose()?,
+        })
+    }
+    fn is_payment(&self) -> bool {
+        self.item_type == NATIVE || self.item_type == ERC20
+    }
+    fn kong(&self, collection: Address) -> Option<i16> {
+        match self.item_type {
+            ERC721 | ERC721_WITH_CRITERIA if self.token == collection => token_id(self.identifier),
+            _ => None,
+        }
+    }
+}
+
+pub struct Seaport {
+    fulfilled: ethabi::Event,
+    exchanges: Vec<Address>,
+    opensea: Vec<Address>,
+}
+impl Seaport {
+    pub fn new() -> anyhow::Result<Self> {
+        let abi = ethabi::Contract::load(File::open("src/utils/seaport_abi.json")?)?;
+        Ok(Seaport {
+            fulfilled: abi.event("OrderFulfilled")?.clone(),
+            exchanges: EXCHANGES
+                .iter()
+                .map(|a| a.parse())
+                .collect::<std::result::Result<_, _>>()?,
+            opensea: OPENSEA_FEE_RECIPIENTS
+                .iter()
+                .map(|a| a.parse())
+                .collect::<std::result::Result<_, _>>()?,
+        })
+    }
+}
+impl LogDecoder for Seaport {
+    fn addresses(&self) -> Vec<Address> {
+        self.exchanges.clone()
+    }
+    fn topics(&self) -> Vec<H256> {
+        vec![self.fulfilled.signature()]
+    }
+    // A listing being bought has the tokens on the offer side and is paid by
+    // the recipient. An offer being accepted is the other way around: the
+    // offerer pays and the recipient hands over the tokens. Matched orders
+    // have no recipient, so their seller is the zero address.
+    fn decode(&self, log: &Log, collection: Address) -> Result<Vec<Fill>> {
+        let params = parse_log(&self.fulfilled, log)?;
+        let offerer = address(param(&params, "offerer")?)?;
+        let recipient = address(param(&params, "recipient")?)?;
+        let offer = array(param(&params, "offer")?)?
+            .into_iter()
+            .map(Item::from_token)
+            .collect::<Result<Vec<_>>>()?;
+        let consideration = array(param(&params, "consideration")?)?
+            .into_iter()
+            .map(Item::from_token)
+            .collect::<Result<Vec<_>>>()?;
+        let marketplace = if consideration
+            .iter()
+            .any(|item| matches!(item.recipient, Some(r) if self.opensea.contains(&r)))
+        {
+            Marketplace::OpenSea
+        } else {
+            Marketplace::Seaport
+        };
+
+        let listed: Vec<i16> = offer.iter().filter_map(|i| i.kong(collection)).collect();
+        let bid: Vec<i16> = consideration
+            .iter()
+            .filter_map(|i| i.kong(collection))
+            .collect();
+        let (tokens, payment, seller, buyer) = match (listed.is_empty(), bid.is_empty()) {
+            (false, _) => (listed, &consideration, offerer, recipient),
+            (true, false) => (bid, &offer, recipient, offerer),
+            (true, true) => return Ok(Vec::new()),
+        };
+        let payments: Vec<&Item> = payment.iter().filter(|i| i.is_payment()).collect();
+        let total = payments
+            .iter()
+            .fold(U256::zero(), |acc, i| acc.saturating_add(i.amount));
+        // Matched orders emit one event per side. The side with the tokens
+        // and nothing to pay is the other half of a sale counted elsewhere.
+        let currency = match payments.first() {
+            Some(item) if !total.is_zero() => item.token,
+            _ => return Ok(Vec::new()),
+        };
+        let order_hash = hash(param(&params, "orderHash")?)?;
+        // Bundles don't say what each token went for, so split the price evenly.
+        let each = total / U256::from(tokens.len());
+        Ok(tokens
+            .into_iter()
+            .map(|token_id| Fill {
+                token_id,
+                marketplace: marketplace.clone(),
+                order_hash: Some(order_hash.clone()),
+                seller,
+                buyer,
+                amount: each,
+                payment_token: payment_token(currency),
+            })
+            .collect())
+    }
+}
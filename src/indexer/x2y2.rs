@@ -0,0 +1,86 @@
+use crate::{
+    error::Result,
+    indexer::{
+        address, array, hash, param, parse_log, payment_token, token_id, tuple, uint, Fill,
+        LogDecoder,
+    },
+    kong_data::Marketplace,
+};
+use ethabi::ParamType;
+use std::fs::File;
+use web3::types::{Address, Log, H256, U256};
+
+const EXCHANGE: &str = "0x74312363e45DCaBA76c59ec49a7Aa8A65a67EeD3";
+// The maker is buying rather than selling.
+const INTENT_BUY: u64 = 3;
+
+pub struct X2Y2 {
+    inventory: ethabi::Event,
+    exchange: Address,
+}
+impl X2Y2 {
+    pub fn new() -> anyhow::Result<Self> {
+        let abi = ethabi::Contract::load(File::open("src/utils/x2y2_abi.json")?)?;
+        Ok(X2Y2 {
+            inventory: abi.event("EvInventory")?.clone(),
+            exchange: EXCHANGE.parse()?,
+        })
+    }
+}
+impl LogDecoder for X2Y2 {
+    fn addresses(&self) -> Vec<Address> {
+        vec![self.exchange]
+    }
+    fn topics(&self) -> Vec<H256> {
+        vec![self.inventory.signature()]
+    }
+    // The traded tokens are ABI encoded (address token, uint256 tokenId)[] in
+    // the order item's data. The settled price is in the settle detail.
+    fn decode(&self, log: &Log, collection: Address) -> Result<Vec<Fill>> {
+        let params = parse_log(&self.inventory, log)?;
+        let item = tuple(param(&params, "item")?)?;
+        let data = item
+            .get(1)
+            .cloned()
+            .and_then(|t| t.into_bytes())
+            .ok_or_else(super::invalid)?;
+        let pair = ParamType::Tuple(vec![ParamType::Address, ParamType::Uint(256)]);
+        let pairs = ethabi::decode(&[ParamType::Array(Box::new(pair))], &data)?;
+        let mut tokens = Vec::new();
+        for pair in array(pairs.into_iter().next().ok_or_else(super::invalid)?)? {
+            let mut pair = tuple(pair)?.into_iter();
+            let token = address(pair.next().ok_or_else(super::invalid)?)?;
+            let id = uint(pair.next().ok_or_else(super::invalid)?)?;
+            if token == collection {
+                tokens.extend(token_id(id));
+            }
+        }
+        if tokens.is_empty() {
+            return Ok(Vec::new());
+        }
+        let detail = tuple(param(&params, "detail")?)?;
+        let price = uint(detail.get(3).cloned().ok_or_else(super::invalid)?)?;
+        let maker = address(param(&params, "maker")?)?;
+        let taker = address(param(&params, "taker")?)?;
+        let (seller, buyer) = if uint(param(&params, "intent")?)? == U256::from(INTENT_BUY) {
+            (taker, maker)
+        } else {
+            (maker, taker)
+        };
+        let order_hash = hash(param(&params, "itemHash")?)?;
+        let currency = payment_token(address(param(&params, "currency")?)?);
+        let each = price / U256::from(tokens.len());
+        Ok(tokens
+            .into_iter()
+            .map(|token_id| Fill {
+                token_id,
+                marketplace: Marketplace::X2Y2,
+                order_hash: Some(order_hash.clone()),
+                seller,
+                buyer,
+                amount: each,
+                payment_token: currency.clone(),
+            })
+            .collect())
+    }
+}
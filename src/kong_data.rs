@@ -1,29 +1,41 @@
 use crate::{
+    cache::CacheStore,
+    config::Config,
+    error::{Error, Result},
+    health::{Health, SyncStatus},
+    indexer::{self, SaleIndexer, SaleLedger, SaleRecord},
+    logging::Progress,
+    metadata::{changed_tokens, MetadataFetcher},
+    metrics::{FLOOR_PRICE, LISTED, MONGO_WRITE_DURATION, TOKENS_UPDATED},
     opensea_client::{
         event::{EventsRequest, EventsResponse},
         listing::{ListingsRequest, ListingsResponse},
-        OpenseaClient,
+        ApiVersion, CollectionListingsRequest, CollectionListingsResponse, HttpMode, HttpOptions,
+        OpenseaClient, OrderSide, OrdersRequest, OrdersResponse, PriceStrategy, Recorder, Replayer,
+        RetryPolicy, V2EventsRequest, V2EventsResponse,
     },
+    oracle::{self, fill_sale_usd, fill_usd, PriceOracle},
+    payment::{self, wei_to_eth, PaymentToken, Rates},
+    reader::{self, TokenReader},
     utils::*,
 };
-use hex_literal;
 use mongodb::{options::ClientOptions, Client, Collection};
-use progress_bar::*;
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
-    env,
-    fs::File,
-    io::{BufReader, BufWriter},
-    time::Instant,
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::{Duration, Instant},
 };
-use web3::transports::{Batch, Http};
+use tracing::{debug, info, info_span, instrument, warn, Instrument};
+use web3::{transports::Http, types::U256};
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
 pub enum Marketplace {
     OpenSea,
     LooksRare,
     X2Y2,
+    // Seaport orders that didn't pay OpenSea's fee, i.e. placed elsewhere.
+    Seaport,
 }
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub enum SaleType {
@@ -31,35 +43,132 @@ pub enum SaleType {
     Auction,
     Bid,
 }
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuctionKind {
+    // Price moves from start to end amount over the listing, usually downwards.
+    Dutch,
+    // Start amount is the reserve. Bids only ever raise it.
+    English,
+}
+// Price schedule of an auction listing. Amounts are in the smallest unit of
+// the sale's payment token and times are unix seconds.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Auction {
+    pub kind: AuctionKind,
+    #[serde(with = "payment::decimal")]
+    pub start_amount: U256,
+    #[serde(with = "payment::decimal")]
+    pub end_amount: U256,
+    pub start_time: u64,
+    pub end_time: u64,
+}
+impl Auction {
+    // Amount the listing asks for at `timestamp`. Dutch auctions interpolate
+    // linearly and round up, the same way Seaport settles them.
+    pub fn amount_at(&self, timestamp: u64) -> U256 {
+        if self.kind == AuctionKind::English || self.start_amount == self.end_amount {
+            return self.start_amount;
+        }
+        if timestamp <= self.start_time || self.end_time <= self.start_time {
+            return self.start_amount;
+        }
+        if timestamp >= self.end_time {
+            return self.end_amount;
+        }
+        let duration = U256::from(self.end_time - self.start_time);
+        let elapsed = U256::from(timestamp - self.start_time);
+        let remaining = duration - elapsed;
+        self.start_amount
+            .checked_mul(remaining)
+            .zip(self.end_amount.checked_mul(elapsed))
+            .and_then(|(start, end)| start.checked_add(end))
+            .and_then(|total| total.checked_add(duration - 1))
+            .map(|total| total / duration)
+            .unwrap_or(self.end_amount)
+    }
+}
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
 pub struct KongTraits {
-    cumulative: i16,
-    shooting: i8,
-    finish: i8,
-    defense: i8,
-    vision: i8,
-    background: String,
-    fur: String,
-    mouth: String,
-    eyes: String,
-    clothes: Option<String>,
-    head: Option<String>,
-    head_accessory: Option<String>,
-    jewellery: Option<String>,
+    pub cumulative: i16,
+    pub shooting: i8,
+    pub finish: i8,
+    pub defense: i8,
+    pub vision: i8,
+    pub background: String,
+    pub fur: String,
+    pub mouth: String,
+    pub eyes: String,
+    pub clothes: Option<String>,
+    pub head: Option<String>,
+    pub head_accessory: Option<String>,
+    pub jewellery: Option<String>,
 }
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Sale {
+    #[serde(default)]
+    pub order_hash: Option<String>,
+    // What the seller asked for, in the smallest unit of `payment_token`.
+    #[serde(default, with = "payment::decimal")]
+    pub amount: U256,
+    #[serde(default)]
+    pub payment_token: PaymentToken,
     pub created_timestamp: u64,
     pub expiration_timestamp: Option<u64>,
     pub sale_type: SaleType,
-    pub price_eth: f64,
+    // Set for auctions whose price schedule is known. `amount` is then the
+    // price as of the last update.
+    #[serde(default)]
+    pub auction: Option<Auction>,
+    // ETH-equivalent of `amount`, exact for ETH and WETH listings.
+    #[serde(with = "payment::decimal")]
+    pub price_wei: U256,
     pub price_usd: Option<f64>,
     pub platform: Marketplace,
 }
+impl Sale {
+    // Fills in the ETH-equivalent price, or None if `amount` can't be converted.
+    pub fn priced(mut self, rates: &Rates) -> Option<Self> {
+        match rates.to_wei(&self.payment_token, self.amount) {
+            Some(wei) => {
+                self.price_wei = wei;
+                Some(self)
+            }
+            None => {
+                warn!(
+                    token = %self.payment_token.symbol,
+                    order_hash = ?self.order_hash,
+                    "No rate for payment token. Skipping listing"
+                );
+                None
+            }
+        }
+    }
+    pub fn price_eth(&self) -> f64 {
+        wei_to_eth(self.price_wei)
+    }
+}
+// Keeps the first sale of every order, and every sale without a hash.
+pub fn dedup_orders(sales: &mut Vec<Sale>) {
+    let mut seen = HashSet::new();
+    sales.retain(|s| match &s.order_hash {
+        Some(hash) => seen.insert(hash.to_lowercase()),
+        None => true,
+    });
+}
+// Whether two listing sets hold the same orders at the same prices.
+fn same_listings(a: &[Sale], b: &[Sale]) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .zip(b)
+            .all(|(a, b)| a.order_hash == b.order_hash && a.price_wei == b.price_wei)
+}
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct KongData {
     pub name: String,
     pub bio: Option<String>,
+    // Holder as of the last info update. Missing in caches written before owners were read.
+    #[serde(default)]
+    pub owner: Option<String>,
     pub traits: KongTraits,
     pub current_sales: Vec<Sale>,
 }
@@ -69,21 +178,131 @@ pub struct Cached {
     data: HashMap<i16, KongData>,
     prev_sales_ts: u64,
     prev_names_ts: u64,
+    #[serde(default)]
+    sales: SaleLedger,
+    #[serde(default)]
+    prev_metadata_ts: u64,
 }
 impl Cached {
-    pub fn default() -> anyhow::Result<Self> {
+    pub fn new() -> anyhow::Result<Self> {
         Ok(Cached {
             data: get_defaults()?,
             prev_sales_ts: 0_u64,
             prev_names_ts: 0_u64,
+            sales: SaleLedger::default(),
+            prev_metadata_ts: 0_u64,
         })
     }
+    pub fn data(&self) -> &HashMap<i16, KongData> {
+        &self.data
+    }
+    pub fn sales(&self) -> &SaleLedger {
+        &self.sales
+    }
+    pub fn data_mut(&mut self) -> &mut HashMap<i16, KongData> {
+        &mut self.data
+    }
+    pub fn floor_price(&self) -> Option<U256> {
+        self.data
+            .values()
+            .flat_map(|d| d.current_sales.iter().map(|s| s.price_wei))
+            .min()
+    }
+    // Moves auction listings to their price at `now` and re-sorts every token's
+    // listings. Listings keep their last price if it can't be converted.
+    pub fn reprice_auctions(&mut self, now: u64, rates: &Rates) -> usize {
+        let mut repriced = 0;
+        for data in self.data.values_mut() {
+            let mut changed = false;
+            for sale in data.current_sales.iter_mut() {
+                let amount = match &sale.auction {
+                    Some(auction) => auction.amount_at(now),
+                    None => continue,
+                };
+                if amount == sale.amount {
+                    continue;
+                }
+                if let Some(wei) = rates.to_wei(&sale.payment_token, amount) {
+                    sale.amount = amount;
+                    sale.price_wei = wei;
+                    changed = true;
+                    repriced += 1;
+                }
+            }
+            if changed {
+                data.current_sales.sort_by_key(|s| s.price_wei);
+            }
+        }
+        repriced
+    }
+    // Drops listings that expired at or before `now`. An expiry of 0 means none.
+    pub fn prune_expired(&mut self, now: u64) -> usize {
+        let mut pruned = 0;
+        for data in self.data.values_mut() {
+            let before = data.current_sales.len();
+            data.current_sales
+                .retain(|s| !matches!(s.expiration_timestamp, Some(exp) if exp > 0 && exp <= now));
+            pruned += before - data.current_sales.len();
+        }
+        pruned
+    }
+    // Hashes are compared ignoring case, like in `dedup_orders`.
+    pub fn remove_order(&mut self, token_id: i16, order_hash: &str) {
+        if let Some(data) = self.data.get_mut(&token_id) {
+            data.current_sales.retain(|s| match &s.order_hash {
+                Some(hash) => !hash.eq_ignore_ascii_case(order_hash),
+                None => true,
+            });
+        }
+    }
+    // A sale moves the token to a new owner, which invalidates every listing
+    // made by the previous one.
+    pub fn clear_sales(&mut self, token_id: i16) {
+        if let Some(data) = self.data.get_mut(&token_id) {
+            data.current_sales.clear();
+        }
+    }
+    pub fn num_listed(&self) -> usize {
+        self.data
+            .values()
+            .filter(|d| !d.current_sales.is_empty())
+            .count()
+    }
+}
+// What the events since the last update mean for the cached sales.
+#[derive(Debug, Default)]
+struct SaleEvents {
+    changed: Vec<i16>,
+    sold: Vec<i16>,
+    cancelled: Vec<(i16, String)>,
+}
+impl SaleEvents {
+    fn add(&mut self, token_id: i16, event_type: &str, order_hash: Option<String>) {
+        self.changed.push(token_id);
+        match (event_type, order_hash) {
+            ("successful" | "sale", _) => self.sold.push(token_id),
+            ("cancelled" | "cancel", Some(hash)) => self.cancelled.push((token_id, hash)),
+            _ => {}
+        }
+    }
 }
 pub struct ScaperBot {
     cached: Cached,
-    web3: web3::Web3<Batch<Http>>,
+    cache_store: CacheStore,
+    tokens: TokenReader,
     os_client: OpenseaClient,
+    mongo_client: Client,
     mongo_coll: Collection<MongoDoc<'static>>,
+    progress_interval: Duration,
+    status: Arc<SyncStatus>,
+    api_version: ApiVersion,
+    collection_slug: String,
+    price_strategy: PriceStrategy,
+    oracle: Arc<dyn PriceOracle>,
+    sale_indexer: Option<SaleIndexer>,
+    sales_coll: Collection<SaleRecord>,
+    metadata: Option<MetadataFetcher>,
+    metadata_interval: Duration,
 }
 #[derive(Serialize, Debug, Clone)]
 
@@ -91,7 +310,10 @@ struct MongoDoc<'a> {
     token_id: i16,
     name: &'a String,
     bio: &'a Option<String>,
+    owner: &'a Option<String>,
     current_price: Option<f64>,
+    // Exact `current_price` in wei, as a decimal string since BSON has no 256-bit integers.
+    current_price_wei: Option<String>,
     cumulative: i16,
     shooting: i8,
     finish: i8,
@@ -107,60 +329,247 @@ struct MongoDoc<'a> {
     jewellery: &'a Option<String>,
 }
 impl ScaperBot {
-    pub async fn init() -> anyhow::Result<Self> {
-        let node_url = env::var("INFURA_MAINNET")?;
-        let os_key = env::var("OS_KEY")?;
-        let mongo_url = env::var("MONGO_URL")?;
-        let client = Client::with_options(ClientOptions::parse(mongo_url).await?)?;
+    pub async fn init(config: &Config) -> anyhow::Result<Self> {
+        let client = Client::with_options(ClientOptions::parse(&config.mongo_url).await?)?;
         let db = client.database("kong-scraper");
         let collection = db.collection::<MongoDoc>("formatted");
-        let c: Cached = if let Ok(cac) = restore_cache(String::from("src/utils/cache.json")) {
-            cac
-        } else {
-            Cached::default()?
+        let sales_coll = db.collection::<SaleRecord>("sales");
+        let mut cache_store = CacheStore::new(
+            config.cache_path.clone(),
+            config.cache_backups,
+            config.cache_fallback_to_backup,
+            config.cache_format,
+        );
+        cache_store.set_backup_interval(config.cache_backup_interval);
+        let c: Cached = match cache_store.restore()? {
+            Some(cac) => cac,
+            None => Cached::new()?,
+        };
+        let status = Arc::new(SyncStatus::default());
+        status.set_prev_sales_ts(c.prev_sales_ts);
+        status.set_prev_names_ts(c.prev_names_ts);
+        if config.os_keys.is_empty() {
+            return Err(anyhow::anyhow!("OS_KEY needs at least one API key"));
+        }
+        let mut os_client = OpenseaClient::new(
+            &config.os_keys,
+            config.opensea_url.as_str(),
+            config.opensea_throttle,
+            config.opensea_backoff,
+        );
+        os_client.set_http_options(&HttpOptions {
+            user_agent: config.opensea_user_agent.clone(),
+            proxy: config.opensea_proxy.clone(),
+            connect_timeout: config.opensea_connect_timeout,
+            ..HttpOptions::default()
+        })?;
+        os_client.set_retry_policy(RetryPolicy {
+            timeout: config.opensea_timeout,
+            max_retries: config.opensea_max_retries,
+            breaker_threshold: config.opensea_breaker_threshold,
+            breaker_cooldown: config.opensea_breaker_cooldown,
+            key_cooldown: config.opensea_key_cooldown,
+        });
+        match (&config.opensea_record, &config.opensea_replay) {
+            (Some(_), Some(_)) => {
+                return Err(anyhow::anyhow!(
+                    "OPENSEA_RECORD and OPENSEA_REPLAY can't both be set"
+                ))
+            }
+            (Some(path), None) => os_client.set_http_mode(HttpMode::Record(Recorder::new(path)?)),
+            (None, Some(path)) => os_client.set_http_mode(HttpMode::Replay(Replayer::load(path)?)),
+            (None, None) => {}
+        }
+        let sale_indexer = match config.index_sales {
+            true => Some(indexer::from_config(config)?),
+            false => None,
         };
         Ok(ScaperBot {
             cached: c,
-            web3: get_web3(node_url.as_str()).expect("couldnt get web3. check node url"),
-            os_client: OpenseaClient::new(os_key.as_str()),
+            cache_store,
+            tokens: TokenReader::new(reader::from_config(config)?)?,
+            os_client,
+            mongo_client: client,
             mongo_coll: collection,
+            progress_interval: config.progress_interval,
+            status,
+            api_version: config.opensea_api,
+            collection_slug: config.opensea_collection.clone(),
+            price_strategy: config.price_strategy,
+            oracle: oracle::from_config(config)?,
+            sale_indexer,
+            sales_coll,
+            metadata: match config.refresh_metadata {
+                true => Some(MetadataFetcher::new(&config.ipfs_gateway)?),
+                false => None,
+            },
+            metadata_interval: config.metadata_interval,
         })
     }
 
+    pub fn health(&self, config: &Config) -> anyhow::Result<Health> {
+        Ok(Health::new(
+            self.mongo_client.clone(),
+            web3::Web3::new(Http::new(config.node_url.as_str())?),
+            self.os_client.clone(),
+            self.status.clone(),
+            config.staleness_threshold,
+        ))
+    }
+
+    pub async fn shutdown(self) -> anyhow::Result<()> {
+        info!("Flushing cache");
+        self._cache_updates()?;
+        self.mongo_client.shutdown().await;
+        Ok(())
+    }
+
     pub fn get_all(&self) -> &Cached {
         &self.cached
     }
 
-    pub async fn update_all(&mut self) -> anyhow::Result<()> {
+    #[instrument(skip_all)]
+    pub async fn update_all(&mut self) -> Result<()> {
         self.update_infos().await?;
         self.update_prices().await?;
         Ok(())
     }
-    pub async fn update_infos(&mut self) -> anyhow::Result<()> {
+    #[instrument(skip_all)]
+    pub async fn update_infos(&mut self) -> Result<()> {
         let current_ts = get_current_ts();
-        self._update_names(None).await?;
-        self._update_bios(None).await?;
+        let block = self.tokens.reader().block_number().await?;
+        info!(block, "Reading token info");
+        self._update_names(None, block).await?;
+        self._update_bios(None, block).await?;
+        self._update_owners(None, block).await?;
         self.cached.prev_names_ts = current_ts;
+        self.status.set_prev_names_ts(current_ts);
         self._cache_updates()?;
         Ok(())
     }
-    pub async fn update_prices(&mut self) -> anyhow::Result<()> {
+    #[instrument(skip_all)]
+    pub async fn update_prices(&mut self) -> Result<()> {
         let current_ts = get_current_ts();
-        self._update_prices().await?;
+        let rates = oracle::rates(&*self.oracle).await;
+        self._update_prices(&rates).await?;
+        let pruned = self.cached.prune_expired(current_ts);
+        if pruned > 0 {
+            info!(pruned, "Pruned expired listings");
+        }
+        let repriced = self.cached.reprice_auctions(current_ts, &rates);
+        if repriced > 0 {
+            info!(repriced, "Repriced auction listings");
+        }
+        // USD prices are nice to have, so a feed outage doesn't fail the update.
+        if let Some(eth_usd) = rates.eth_usd() {
+            for data in self.cached.data.values_mut() {
+                fill_usd(&mut data.current_sales, eth_usd);
+            }
+        }
         self.cached.prev_sales_ts = current_ts;
+        self.status.set_prev_sales_ts(current_ts);
+        if let Some(floor) = self.cached.floor_price() {
+            FLOOR_PRICE.set(wei_to_eth(floor));
+        }
+        LISTED.set(self.cached.num_listed() as i64);
+        self._cache_updates()?;
+        Ok(())
+    }
+    // Indexes on-chain sales up to the chain head. Does nothing unless enabled.
+    #[instrument(skip_all)]
+    pub async fn update_sales(&mut self) -> Result<()> {
+        let indexer = match &self.sale_indexer {
+            Some(indexer) => indexer,
+            None => return Ok(()),
+        };
+        let poll = indexer.poll(&mut self.cached.sales).await?;
+        info!(
+            head = poll.head,
+            indexed = poll.indexed,
+            finalized = poll.finalized,
+            rolled_back = poll.rolled_back,
+            "Sales indexed"
+        );
+        self._cache_updates()?;
+        Ok(())
+    }
+    // Reloads traits from each token's metadata, at most once per interval.
+    // Does nothing unless enabled.
+    #[instrument(skip_all)]
+    pub async fn update_metadata(&mut self) -> Result<()> {
+        let fetcher = match &self.metadata {
+            Some(fetcher) => fetcher,
+            None => return Ok(()),
+        };
+        let current_ts = get_current_ts();
+        if current_ts.saturating_sub(self.cached.prev_metadata_ts)
+            < self.metadata_interval.as_secs()
+        {
+            return Ok(());
+        }
+        info!("Updating metadata");
+        let block = self.tokens.reader().block_number().await?;
+        let ids = ids_or_all(None);
+        let uris = self.tokens.token_uris(&ids, block).await?;
+        let fetched = fetcher
+            .fetch_traits(
+                ids.into_iter()
+                    .zip(uris)
+                    .filter_map(|(id, uri)| uri.map(|u| (id, u)))
+                    .collect(),
+            )
+            .await;
+        // Leaves the last refresh time alone, so the next cycle tries again
+        // rather than waiting out a whole interval.
+        if fetched.is_empty() {
+            return Err(Error::Metadata(String::from(
+                "no token metadata could be loaded",
+            )));
+        }
+        let changed = changed_tokens(&self.cached.data, &fetched);
+        for id in &changed {
+            if let Some(data) = self.cached.data.get_mut(id) {
+                data.traits = fetched[id].clone();
+            }
+        }
+        TOKENS_UPDATED
+            .with_label_values(&["metadata"])
+            .set(changed.len() as i64);
+        debug!(changed = ?changed, "Tokens with new traits");
+        info!(
+            fetched = fetched.len(),
+            changed = changed.len(),
+            "Metadata updated"
+        );
+        self.cached.prev_metadata_ts = current_ts;
+        self._cache_updates()?;
+        Ok(())
+    }
+    // Writes final sales to the DB, priced in USD as of when they happened.
+    // They're dropped from the cache once stored.
+    #[instrument(skip_all)]
+    pub async fn upload_sales(&mut self) -> Result<()> {
+        if self.cached.sales.finalized().is_empty() {
+            return Ok(());
+        }
+        fill_sale_usd(&*self.oracle, self.cached.sales.finalized_mut()).await;
+        let sales = self.cached.sales.finalized();
+        self.sales_coll.insert_many(sales, None).await?;
+        info!(sales = sales.len(), "Uploaded sales");
+        self.cached.sales.take_finalized();
         self._cache_updates()?;
         Ok(())
     }
-    pub async fn upload_to_db<'a>(&'a self) -> anyhow::Result<()> {
-        println!("Updating DB");
+    #[instrument(skip_all)]
+    pub async fn upload_to_db<'a>(&'a self) -> Result<()> {
+        info!("Updating DB");
         let format_data_to_doc = |data: &'a KongData, id: &i16| MongoDoc {
             token_id: *id,
             name: &data.name,
             bio: &data.bio,
-            current_price: match data.current_sales.len() {
-                0 => None,
-                _ => Some(data.current_sales[0].price_eth),
-            },
+            owner: &data.owner,
+            current_price: data.current_sales.first().map(Sale::price_eth),
+            current_price_wei: data.current_sales.first().map(|s| s.price_wei.to_string()),
             cumulative: data.traits.cumulative,
             shooting: data.traits.shooting,
             finish: data.traits.finish,
@@ -176,6 +585,7 @@ impl ScaperBot {
             jewellery: &data.traits.jewellery,
         };
 
+        let start = Instant::now();
         self.mongo_coll.drop(None).await?;
         let mut to_upload: Vec<MongoDoc> = Vec::new();
         for i in 0..10_000 {
@@ -186,6 +596,7 @@ impl ScaperBot {
             ));
         }
         self.mongo_coll.insert_many(&to_upload, None).await?;
+        MONGO_WRITE_DURATION.observe(start.elapsed().as_secs_f64());
         /* let mut out_vec: Vec<String> = vec!["token_id,name,bio,current_price(eth),cumulative,shooting,finish,defense,vision,background,fur,mouth,eyes,clothes,head,head_accessory,jewellery".to_string()];
         for elem in to_upload {
             out_vec.push(format!(
@@ -215,232 +626,269 @@ impl ScaperBot {
 
         Ok(())
     }
-    async fn _update_prices(&mut self) -> anyhow::Result<()> {
+    async fn _update_prices(&mut self, rates: &Rates) -> Result<()> {
+        if self.price_strategy == PriceStrategy::Bulk {
+            return self._update_prices_bulk(rates).await;
+        }
         let start = Instant::now();
-        println!("Updating prices!");
-        let mut to_update: Vec<i16> = self._get_ids_to_update().await?;
+        info!("Updating prices");
+        let events = self._get_sale_events().await?;
+        // Drop what the events already rule out, so a failed or skipped refetch
+        // can't leave sold or cancelled orders behind.
+        for id in &events.sold {
+            self.cached.clear_sales(*id);
+        }
+        for (id, hash) in &events.cancelled {
+            self.cached.remove_order(*id, hash);
+        }
+        let mut to_update: Vec<i16> = events.changed;
         let len = &to_update.len();
-        println!(
-            "Got tokenIds to update.\ntotal: {}\nUpdating prices now.",
-            len
-        );
-        if to_update.len() < 1 {
+        info!(total = len, "Got token ids to update");
+        TOKENS_UPDATED
+            .with_label_values(&["prices"])
+            .set(*len as i64);
+        if to_update.is_empty() {
             Ok(())
         } else {
-            init_progress_bar(to_update.len());
-            set_progress_bar_action("Price Update", Color::Blue, Style::Bold);
-            let mut listing_req: ListingsRequest =
-                ListingsRequest::new(get_contract_address(), 0_i16, None);
-            while to_update.len() > 0 {
-                listing_req.set_token_id(to_update.remove(0));
-                let res: ListingsResponse = self.os_client.request(&listing_req).await?;
-                let listings = res.format_listing();
+            let mut progress = Progress::new("Price Update", *len, self.progress_interval);
+            while !to_update.is_empty() {
+                let token_id = to_update.remove(0);
+                let listings = match self
+                    ._get_listings(token_id, rates)
+                    .instrument(info_span!("token", token_id))
+                    .await
+                {
+                    Ok(l) => l,
+                    // Burned or hidden tokens have no asset page, so nothing is listed.
+                    Err(err) if err.is_not_found() => {
+                        warn!(token_id, "Token not found");
+                        Vec::new()
+                    }
+                    Err(err) => return Err(err),
+                };
                 self.cached
                     .data
-                    .entry(listing_req.token_id)
+                    .entry(token_id)
                     .and_modify(|prev| prev.current_sales = listings);
-                inc_progress_bar();
+                progress.inc();
             }
-            finalize_progress_bar();
-            println!(
-                "Prices updated!\nNumber of updates: {}\nTime elapsed: {} Seconds!\nAverage time per update: {}",&len,start.elapsed().as_secs(),(start.elapsed().as_secs_f64()/ ((i64::try_from(len.clone()).ok().unwrap()) as f64)));
+            progress.finish();
+            info!(
+                updates = len,
+                elapsed_secs = start.elapsed().as_secs(),
+                avg_secs_per_update = start.elapsed().as_secs_f64() / *len as f64,
+                "Prices updated"
+            );
             Ok(())
         }
     }
-    async fn _get_ids_to_update(&self) -> anyhow::Result<Vec<i16>> {
-        println!("Getting tokenIds to update");
-        let mut ids: Vec<i16> = Vec::new();
-        let mut event_req = EventsRequest::new(
-            get_contract_address(),
-            Some("created".to_string()),
-            None,
-            None,
-            Some(self.cached.prev_sales_ts),
-            None,
-        );
-        let mut calls = 0;
+    // Rebuilds every token's listings from the collection's active listings.
+    // Tokens missing from the listings had theirs filled or cancelled.
+    #[instrument(skip_all)]
+    async fn _update_prices_bulk(&mut self, rates: &Rates) -> Result<()> {
+        let start = Instant::now();
+        info!("Fetching all collection listings");
+        let mut by_token: HashMap<i16, Vec<Sale>> = HashMap::new();
+        let mut listings_req = CollectionListingsRequest::new(self.collection_slug.clone());
+        let mut pages = 0;
         loop {
-            let res: EventsResponse = self
-                .os_client
-                .request::<EventsRequest, EventsResponse>(&event_req)
-                .await?;
-            calls += 1;
-            if calls >= 150 {
-                break;
-            }
-            for event in &res.asset_events {
-                if let Some(ass) = &event.asset {
-                    ids.push(ass.token_id);
+            let res: CollectionListingsResponse = self.os_client.request(&listings_req).await?;
+            pages += 1;
+            for listing in &res.listings {
+                let sale = match listing.to_sale() {
+                    Ok(sale) => sale.priced(rates),
+                    Err(err) => {
+                        warn!(order_hash = %listing.order_hash, error = %err, "Couldnt parse listing price. Skipping listing");
+                        None
+                    }
+                };
+                if let (Some(id), Some(sale)) = (listing.token_id(), sale) {
+                    by_token.entry(id).or_default().push(sale);
                 }
             }
-            if res.asset_events.len() < 1 {
+            if res.listings.is_empty() || res.next.is_none() {
                 break;
             }
-            if let Some(cur) = res.next {
-                event_req.set_cursor(Some(cur));
-            } else {
-                break;
+            listings_req.set_cursor(res.next);
+        }
+        info!(pages, listed = by_token.len(), "Got collection listings");
+        let mut changed = 0;
+        for (id, data) in self.cached.data.iter_mut() {
+            let mut listings = by_token.remove(id).unwrap_or_default();
+            dedup_orders(&mut listings);
+            listings.sort_by_key(|s| s.price_wei);
+            if !same_listings(&listings, &data.current_sales) {
+                changed += 1;
             }
+            data.current_sales = listings;
         }
-        println!("Got created events. Now parsing successful events");
-        event_req.set_cursor(None);
-        event_req.set_event_type("successful".to_string());
-        calls = 0;
-        loop {
-            let res: EventsResponse = self
-                .os_client
-                .request::<EventsRequest, EventsResponse>(&event_req)
-                .await?;
-            calls += 1;
-            if calls >= 150 {
-                break;
+        TOKENS_UPDATED.with_label_values(&["prices"]).set(changed);
+        info!(
+            updates = changed,
+            elapsed_secs = start.elapsed().as_secs(),
+            "Prices updated"
+        );
+        Ok(())
+    }
+    async fn _get_listings(&self, token_id: i16, rates: &Rates) -> Result<Vec<Sale>> {
+        match self.api_version {
+            ApiVersion::V1 => {
+                let listing_req = ListingsRequest::new(get_contract_address(), token_id, None);
+                let res: ListingsResponse = self.os_client.request(&listing_req).await?;
+                Ok(res.format_listing(rates))
             }
-            for event in &res.asset_events {
-                if let Some(ass) = &event.asset {
-                    ids.push(ass.token_id);
+            ApiVersion::V2 => {
+                let mut orders_req =
+                    OrdersRequest::new(OrderSide::Listings, get_contract_address(), vec![token_id]);
+                let mut listings = Vec::new();
+                loop {
+                    let res: OrdersResponse = self.os_client.request(&orders_req).await?;
+                    listings.append(&mut res.format_listing(rates));
+                    if res.next.is_none() {
+                        break;
+                    }
+                    orders_req.set_cursor(res.next);
                 }
-            }
-            if res.asset_events.len() < 1 {
-                break;
-            }
-            if let Some(cur) = res.next {
-                event_req.set_cursor(Some(cur));
-            } else {
-                break;
+                dedup_orders(&mut listings);
+                listings.sort_by_key(|s| s.price_wei);
+                Ok(listings)
             }
         }
-        event_req.set_cursor(None);
-        event_req.set_event_type("cancelled".to_string());
-        calls = 0;
-        loop {
-            let res: EventsResponse = self
-                .os_client
-                .request::<EventsRequest, EventsResponse>(&event_req)
-                .await?;
-            calls += 1;
-            if calls >= 150 {
-                break;
-            }
-            for event in &res.asset_events {
-                if let Some(ass) = &event.asset {
-                    ids.push(ass.token_id);
+    }
+    #[instrument(skip_all)]
+    async fn _get_sale_events(&self) -> Result<SaleEvents> {
+        if self.api_version == ApiVersion::V2 {
+            return self._get_sale_events_v2().await;
+        }
+        info!("Getting token ids to update");
+        let mut events = SaleEvents::default();
+        let mut event_req = EventsRequest::new(
+            get_contract_address(),
+            None,
+            None,
+            None,
+            Some(self.cached.prev_sales_ts),
+            None,
+        );
+        for event_type in ["created", "successful", "cancelled"] {
+            event_req.set_cursor(None);
+            event_req.set_event_type(event_type.to_string());
+            for _ in 0..150 {
+                let res: EventsResponse = self
+                    .os_client
+                    .request::<EventsRequest, EventsResponse>(&event_req)
+                    .await?;
+                for event in &res.asset_events {
+                    if let Some(ass) = &event.asset {
+                        events.add(ass.token_id, event_type, event.order_hash.clone());
+                    }
                 }
+                if res.asset_events.is_empty() || res.next.is_none() {
+                    break;
+                }
+                event_req.set_cursor(res.next);
             }
-            if res.asset_events.len() < 1 {
-                break;
-            }
-            if let Some(cur) = res.next {
-                event_req.set_cursor(Some(cur));
-            } else {
-                break;
+        }
+        events.changed.sort_unstable();
+        events.changed.dedup();
+
+        Ok(events)
+    }
+    // Same as v1: any token that was listed, sold or had a listing cancelled
+    // since the last update.
+    async fn _get_sale_events_v2(&self) -> Result<SaleEvents> {
+        info!("Getting token ids to update");
+        let mut events = SaleEvents::default();
+        for event_type in ["listing", "sale", "cancel"] {
+            let mut event_req = V2EventsRequest::new(
+                self.collection_slug.clone(),
+                event_type.to_string(),
+                Some(self.cached.prev_sales_ts),
+            );
+            for _ in 0..150 {
+                let res: V2EventsResponse = self.os_client.request(&event_req).await?;
+                for event in &res.asset_events {
+                    if let Some(id) = event.token_id() {
+                        events.add(id, event_type, event.order_hash.clone());
+                    }
+                }
+                if res.asset_events.is_empty() || res.next.is_none() {
+                    break;
+                }
+                event_req.set_cursor(res.next);
             }
         }
-        ids.dedup();
+        events.changed.sort_unstable();
+        events.changed.dedup();
 
-        Ok(ids)
+        Ok(events)
     }
-    async fn _update_names(&mut self, token_ids: Option<Vec<i16>>) -> anyhow::Result<()> {
+    #[instrument(skip_all)]
+    async fn _update_names(&mut self, token_ids: Option<Vec<i16>>, block: u64) -> Result<()> {
         let start = Instant::now();
-        println!("Updating names!");
-        self.web3.transport().submit_batch().await?;
-        let mut ids = if let Some(i) = token_ids {
-            i
-        } else {
-            let mut counter = 0;
-            (0..10_000)
-                .map(|_| {
-                    counter += 1;
-                    i16::try_from(counter - 1).ok().unwrap()
-                })
-                .collect()
-        };
-        ids.dedup();
-        let reader = BufReader::new(File::open("src/utils/kong_naming_abi.json")?);
-        let con: ethabi::Contract = serde_json::from_reader(reader)?;
-        let func: &ethabi::Function = con.function("names")?;
-        let get_call_req = |id: &i16| {
-            let mut builder = web3::types::CallRequest::builder();
-            let b: web3::types::Bytes = func
-                .encode_input(vec![ethabi::Token::Uint(id.clone().into())].as_slice())
-                .unwrap()
-                .into();
-            builder = builder.data(b);
-            builder =
-                builder.to(hex_literal::hex!("02afD7FD5B1C190506F538B36e7741a2F33D715d").into());
-            builder.build()
-        };
-        for id in &ids {
-            self.web3.eth().call(get_call_req(id), None);
-        }
-        let res = self.web3.transport().submit_batch().await?;
-        for (index, elem) in res.iter().enumerate() {
-            let curr_id: i16 = ids[index];
+        info!("Updating names");
+        let ids = ids_or_all(token_ids);
+        let names = self.tokens.names(&ids, block).await?;
+        TOKENS_UPDATED
+            .with_label_values(&["names"])
+            .set(names.len() as i64);
+        for (curr_id, name) in ids.iter().zip(names) {
             self.cached
                 .data
-                .entry(curr_id)
-                .and_modify(|prev| prev.name = parse_name(elem, &curr_id));
+                .entry(*curr_id)
+                .and_modify(|prev| prev.name = name);
         }
-        println!(
-            "Names updated!\nTime elapsed: {} Seconds!",
-            start.elapsed().as_secs()
-        );
+        info!(elapsed_secs = start.elapsed().as_secs(), "Names updated");
 
         Ok(())
     }
-    async fn _update_bios(&mut self, token_ids: Option<Vec<i16>>) -> anyhow::Result<()> {
+    #[instrument(skip_all)]
+    async fn _update_bios(&mut self, token_ids: Option<Vec<i16>>, block: u64) -> Result<()> {
         let start = Instant::now();
-        println!("Updating bios!");
-
-        self.web3.transport().submit_batch().await?;
-        let mut ids = if let Some(i) = token_ids {
-            i
-        } else {
-            let mut counter = 0;
-            (0..10_000)
-                .map(|_| {
-                    counter += 1;
-                    i16::try_from(counter - 1).ok().unwrap()
-                })
-                .collect()
-        };
-        ids.dedup();
-        let reader = BufReader::new(File::open("src/utils/kong_naming_abi.json")?);
-        let con: ethabi::Contract = serde_json::from_reader(reader)?;
-        let func: &ethabi::Function = con.function("bios")?;
-        let get_call_req = |id: &i16| {
-            let mut builder = web3::types::CallRequest::builder();
-            let b: web3::types::Bytes = func
-                .encode_input(vec![ethabi::Token::Uint(id.clone().into())].as_slice())
-                .unwrap()
-                .into();
-            builder = builder.data(b);
-            builder =
-                builder.to(hex_literal::hex!("02afD7FD5B1C190506F538B36e7741a2F33D715d").into());
-            builder.build()
-        };
-        for id in &ids {
-            self.web3.eth().call(get_call_req(id), None);
-        }
-        let res = self.web3.transport().submit_batch().await?;
-        for (index, elem) in res.iter().enumerate() {
-            let curr_id: i16 = ids[index];
+        info!("Updating bios");
+        let ids = ids_or_all(token_ids);
+        let bios = self.tokens.bios(&ids, block).await?;
+        TOKENS_UPDATED
+            .with_label_values(&["bios"])
+            .set(bios.len() as i64);
+        for (curr_id, bio) in ids.iter().zip(bios) {
             self.cached
                 .data
-                .entry(curr_id)
-                .and_modify(|prev| prev.bio = parse_bio(elem));
+                .entry(*curr_id)
+                .and_modify(|prev| prev.bio = bio);
         }
-        println!(
-            "Bios updated!\nTime elapsed: {} Seconds!",
-            start.elapsed().as_secs()
-        );
+        info!(elapsed_secs = start.elapsed().as_secs(), "Bios updated");
 
         Ok(())
     }
+    #[instrument(skip_all)]
+    async fn _update_owners(&mut self, token_ids: Option<Vec<i16>>, block: u64) -> Result<()> {
+        let start = Instant::now();
+        info!("Updating owners");
+        let ids = ids_or_all(token_ids);
+        let owners = self.tokens.owners(&ids, block).await?;
+        TOKENS_UPDATED
+            .with_label_values(&["owners"])
+            .set(owners.len() as i64);
+        for (curr_id, owner) in ids.iter().zip(owners) {
+            self.cached
+                .data
+                .entry(*curr_id)
+                .and_modify(|prev| prev.owner = owner);
+        }
+        info!(elapsed_secs = start.elapsed().as_secs(), "Owners updated");
 
-    fn _cache_updates(&self) -> anyhow::Result<()> {
-        let writer = BufWriter::new(File::create("src/utils/cache.json")?);
-        serde_json::to_writer_pretty(writer, &self.cached)?;
         Ok(())
     }
+    fn _cache_updates(&self) -> Result<()> {
+        self.cache_store.save(&self.cached).map_err(Error::storage)
+    }
+}
+
+// The ids asked for, or every token in the collection.
+fn ids_or_all(token_ids: Option<Vec<i16>>) -> Vec<i16> {
+    let mut ids = token_ids.unwrap_or_else(|| (0..10_000).collect());
+    ids.dedup();
+    ids
 }
 // https://us-east-1.aws.data.mongodb-api.com/app/google-blnmi/endpoint/kongdata
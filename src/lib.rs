@@ -0,0 +1,15 @@
+pub mod cache;
+pub mod config;
+pub mod error;
+pub mod health;
+pub mod indexer;
+pub mod kong_data;
+pub mod logging;
+pub mod metadata;
+pub mod metrics;
+pub mod opensea_client;
+pub mod oracle;
+pub mod payment;
+pub mod reader;
+pub mod server;
+pub mod utils;
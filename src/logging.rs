@@ -0,0 +1,65 @@
+use progress_bar::*;
+use std::{
+    io::{stdout, IsTerminal},
+    time::{Duration, Instant},
+};
+use tracing::info;
+use tracing_subscriber::EnvFilter;
+
+// Levels come from RUST_LOG (default "info"). JSON output is meant for log
+// aggregators such as Heroku's, plain text for local runs.
+pub fn init(json: bool) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    if json {
+        builder.json().with_current_span(true).init();
+    } else {
+        builder.init();
+    }
+}
+
+// Draws a progress bar on a TTY and otherwise logs progress every `interval`.
+pub struct Progress {
+    job: &'static str,
+    total: usize,
+    done: usize,
+    tty: bool,
+    interval: Duration,
+    last_report: Instant,
+}
+impl Progress {
+    pub fn new(job: &'static str, total: usize, interval: Duration) -> Self {
+        let tty = stdout().is_terminal();
+        if tty {
+            init_progress_bar(total);
+            set_progress_bar_action(job, Color::Blue, Style::Bold);
+        }
+        Progress {
+            job,
+            total,
+            done: 0,
+            tty,
+            interval,
+            last_report: Instant::now(),
+        }
+    }
+    pub fn inc(&mut self) {
+        self.done += 1;
+        if self.tty {
+            inc_progress_bar();
+        } else if self.last_report.elapsed() >= self.interval {
+            self.last_report = Instant::now();
+            info!(
+                job = self.job,
+                done = self.done,
+                total = self.total,
+                "progress"
+            );
+        }
+    }
+    pub fn finish(self) {
+        if self.tty {
+            finalize_progress_bar();
+        }
+    }
+}
@@ -1,36 +1,188 @@
-pub mod kong_data;
-pub mod opensea_client;
-pub mod utils;
-
 use dotenv::dotenv;
-use kong_data::ScaperBot;
-use std::time::Duration;
-use tokio::{task, time};
+use kong_scraper::{
+    cache::{convert_cache, CacheFormat},
+    config::{env_or, Config},
+    error::Error,
+    indexer,
+    kong_data::ScaperBot,
+    logging,
+    metadata::{write_metadata_json, MetadataFetcher},
+    metrics::SCRAPER_ERRORS,
+    reader::{self, TokenReader},
+    server,
+};
+use std::{env, net::SocketAddr, sync::Arc};
+use tokio::{signal, time};
+use tracing::{error, info, warn};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     dotenv().ok();
+    logging::init(env_or("LOG_FORMAT", String::from("text"))? == "json");
 
-    let mut scraper = ScaperBot::init().await?;
-    let updates = task::spawn(async move {
-        let mut interval = time::interval(Duration::new(300, 0));
-        loop {
-            interval.tick().await;
-            println!("Updating Collection");
-            match scraper.update_all().await {
-                Ok(_) => println!("Successfully updated prices"),
-                Err(err) => println!("Error updating prices.\nError: {}", err),
-            };
-            match scraper.upload_to_db().await {
-                Ok(_) => println!("Successfully uploaded to DB"),
-                Err(err) => println!("Error uploading to DB.\nError: {}", err),
-            };
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("convert-cache") {
+        if args.len() != 5 {
+            return Err(anyhow::anyhow!(
+                "Usage: kong-scraper convert-cache <input> <output> <json|binary>"
+            ));
+        }
+        convert_cache(&args[2], &args[3], args[4].parse::<CacheFormat>()?)?;
+        info!(input = %args[2], output = %args[3], "Converted cache");
+        return Ok(());
+    }
+    if args.get(1).map(String::as_str) == Some("index-sales") {
+        if args.len() != 4 {
+            return Err(anyhow::anyhow!(
+                "Usage: kong-scraper index-sales <from block> <to block>"
+            ));
+        }
+        let indexer = indexer::from_config(&Config::chain_from_env()?)?;
+        for sale in indexer.sales(args[2].parse()?, args[3].parse()?).await? {
+            println!("{}", serde_json::to_string(&sale)?);
+        }
+        return Ok(());
+    }
+    if args.get(1).map(String::as_str) == Some("snapshot") {
+        if args.len() < 4 {
+            return Err(anyhow::anyhow!(
+                "Usage: kong-scraper snapshot <block|time> <block number|unix seconds> [token ids]"
+            ));
+        }
+        let reader = reader::from_config(&Config::chain_from_env()?)?;
+        let block = match args[2].as_str() {
+            "block" => args[3].parse()?,
+            "time" => reader
+                .block_at(args[3].parse()?)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("No block at or before {}", args[3]))?,
+            other => return Err(anyhow::anyhow!("Unknown snapshot point: {}", other)),
+        };
+        let ids: Vec<i16> = match args.len() {
+            4 => (0..10_000).collect(),
+            _ => args[4..]
+                .iter()
+                .map(|id| id.parse())
+                .collect::<Result<_, _>>()?,
+        };
+        let snapshot = TokenReader::new(reader)?.snapshot(&ids, block).await?;
+        println!("{}", serde_json::to_string(&snapshot)?);
+        return Ok(());
+    }
+    if args.get(1).map(String::as_str) == Some("fetch-metadata") {
+        if args.len() != 3 {
+            return Err(anyhow::anyhow!(
+                "Usage: kong-scraper fetch-metadata <output>"
+            ));
+        }
+        let config = Config::chain_from_env()?;
+        let tokens = TokenReader::new(reader::from_config(&config)?)?;
+        let fetcher = MetadataFetcher::new(&config.ipfs_gateway)?;
+        let ids: Vec<i16> = (0..10_000).collect();
+        let block = tokens.reader().block_number().await?;
+        let uris = tokens.token_uris(&ids, block).await?;
+        let traits = fetcher
+            .fetch_traits(
+                ids.into_iter()
+                    .zip(uris)
+                    .filter_map(|(id, uri)| uri.map(|u| (id, u)))
+                    .collect(),
+            )
+            .await;
+        if traits.len() != 10_000 {
+            warn!(tokens = traits.len(), "Some tokens have no metadata");
+        }
+        write_metadata_json(&args[2], &traits)?;
+        info!(output = %args[2], tokens = traits.len(), "Wrote metadata");
+        return Ok(());
+    }
+
+    let config = Config::from_env()?;
+    let mut scraper = ScaperBot::init(&config).await?;
+    let health = Arc::new(scraper.health(&config)?);
+    let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
+    tokio::spawn(async move {
+        if let Err(err) = server::serve(addr, health).await {
+            error!(error = %err, "Metrics server stopped");
         }
     });
-    updates.await?;
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+    let mut interval = time::interval(config.update_interval);
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => break,
+            _ = interval.tick() => {}
+        }
+        let cycle = run_cycle(&mut scraper);
+        tokio::pin!(cycle);
+        tokio::select! {
+            _ = &mut cycle => {}
+            _ = &mut shutdown => {
+                info!(
+                    deadline_secs = config.shutdown_timeout.as_secs(),
+                    "Shutdown requested. Waiting for current update"
+                );
+                if time::timeout(config.shutdown_timeout, &mut cycle).await.is_err() {
+                    warn!("Shutdown deadline reached. Cancelling current update");
+                }
+                break;
+            }
+        }
+    }
+    scraper.shutdown().await?;
+    info!("Shutdown complete");
 
     Ok(())
 }
+
+async fn run_cycle(scraper: &mut ScaperBot) {
+    info!("Updating Collection");
+    match scraper.update_all().await {
+        Ok(_) => info!("Successfully updated prices"),
+        Err(err) => report_error("update", &err, "Error updating prices"),
+    };
+    match scraper.upload_to_db().await {
+        Ok(_) => info!("Successfully uploaded to DB"),
+        Err(err) => report_error("upload", &err, "Error uploading to DB"),
+    };
+    if let Err(err) = scraper.update_sales().await {
+        report_error("sales", &err, "Error indexing sales");
+    }
+    if let Err(err) = scraper.upload_sales().await {
+        report_error("sales_upload", &err, "Error uploading sales");
+    }
+    if let Err(err) = scraper.update_metadata().await {
+        report_error("metadata", &err, "Error updating metadata");
+    }
+}
+
+fn report_error(job: &str, err: &Error, msg: &str) {
+    SCRAPER_ERRORS.with_label_values(&[job, err.kind()]).inc();
+    error!(
+        error = %err,
+        kind = err.kind(),
+        retryable = err.is_retryable(),
+        "{}",
+        msg
+    );
+}
+
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        let mut sigterm = signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("couldnt install SIGTERM handler");
+        tokio::select! {
+            _ = signal::ctrl_c() => {}
+            _ = sigterm.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    signal::ctrl_c()
+        .await
+        .expect("couldnt install ctrl-c handler");
+}
 /*
 #[get("/")]
 async fn update_prices() -> io::Result<String> {
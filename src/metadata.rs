@@ -0,0 +1,200 @@
+use crate::{
+    error::{Error, Result},
+    kong_data::{KongData, KongTraits},
+};
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::Semaphore;
+use tracing::{info, warn};
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Attribute {
+    pub trait_type: String,
+    pub value: Value,
+}
+// The ERC-721 metadata JSON a token URI points to.
+#[derive(Deserialize, Debug, Clone)]
+pub struct TokenMetadata {
+    pub name: Option<String>,
+    pub image: Option<String>,
+    #[serde(default)]
+    pub attributes: Vec<Attribute>,
+}
+impl TokenMetadata {
+    // Reads the Kong's traits out of its attributes. Appearance traits that a
+    // Kong doesn't have are simply left out of the attributes.
+    pub fn traits(&self) -> Result<KongTraits> {
+        let shooting = self.stat("shooting")?;
+        let finish = self.stat("finish")?;
+        let defense = self.stat("defense")?;
+        let vision = self.stat("vision")?;
+        let cumulative = match self.attribute("cumulative") {
+            Some(_) => self.number("cumulative")?,
+            None => [shooting, finish, defense, vision]
+                .iter()
+                .map(|s| *s as i64)
+                .sum(),
+        };
+        Ok(KongTraits {
+            cumulative: i16::try_from(cumulative).map_err(|_| out_of_range("cumulative"))?,
+            shooting,
+            finish,
+            defense,
+            vision,
+            background: self.required("background")?,
+            fur: self.required("fur")?,
+            mouth: self.required("mouth")?,
+            eyes: self.required("eyes")?,
+            clothes: self.text("clothes"),
+            head: self.text("head"),
+            head_accessory: self.text("head accessory"),
+            jewellery: self.text("jewellery").or_else(|| self.text("jewelry")),
+        })
+    }
+
+    // Trait types are matched ignoring case, and `_` is read as a space.
+    fn attribute(&self, name: &str) -> Option<&Value> {
+        self.attributes
+            .iter()
+            .find(|a| a.trait_type.to_lowercase().replace('_', " ") == name)
+            .map(|a| &a.value)
+    }
+    fn text(&self, name: &str) -> Option<String> {
+        match self.attribute(name)? {
+            Value::String(s) if s.is_empty() || s.eq_ignore_ascii_case("none") => None,
+            Value::String(s) => Some(s.clone()),
+            Value::Null => None,
+            other => Some(other.to_string()),
+        }
+    }
+    fn required(&self, name: &str) -> Result<String> {
+        self.text(name)
+            .ok_or_else(|| Error::Metadata(format!("missing {}", name)))
+    }
+    fn number(&self, name: &str) -> Result<i64> {
+        let value = self
+            .attribute(name)
+            .ok_or_else(|| Error::Metadata(format!("missing {}", name)))?;
+        match value {
+            Value::Number(n) => n.as_i64(),
+            Value::String(s) => s.trim().parse().ok(),
+            _ => None,
+        }
+        .ok_or_else(|| Error::Metadata(format!("{} isnt a number: {}", name, value)))
+    }
+    fn stat(&self, name: &str) -> Result<i8> {
+        i8::try_from(self.number(name)?).map_err(|_| out_of_range(name))
+    }
+}
+
+fn out_of_range(name: &str) -> Error {
+    Error::Metadata(format!("{} is out of range", name))
+}
+
+// Fetches token metadata over HTTP. IPFS URIs are read through `gateway`.
+#[derive(Clone)]
+pub struct MetadataFetcher {
+    client: Client,
+    gateway: String,
+    concurrency: usize,
+}
+impl MetadataFetcher {
+    pub fn new(gateway: &str) -> anyhow::Result<Self> {
+        Ok(MetadataFetcher {
+            client: Client::builder().timeout(Duration::from_secs(30)).build()?,
+            gateway: gateway.trim_end_matches('/').to_string(),
+            concurrency: 8,
+        })
+    }
+
+    pub fn set_concurrency(&mut self, concurrency: usize) {
+        self.concurrency = concurrency.max(1);
+    }
+
+    // The HTTP url to fetch `uri` from.
+    pub fn resolve(&self, uri: &str) -> Result<String> {
+        if let Some(path) = uri.strip_prefix("ipfs://") {
+            let path = path.strip_prefix("ipfs/").unwrap_or(path);
+            return Ok(format!("{}/ipfs/{}", self.gateway, path));
+        }
+        if uri.starts_with("http://") || uri.starts_with("https://") {
+            return Ok(uri.to_string());
+        }
+        Err(Error::Metadata(format!("unsupported token URI: {}", uri)))
+    }
+
+    pub async fn fetch(&self, uri: &str) -> Result<TokenMetadata> {
+        let res = self.client.get(self.resolve(uri)?).send().await?;
+        let status = res.status();
+        let body = res.text().await?;
+        if status != StatusCode::OK {
+            return Err(Error::http_status(status.as_u16(), &body));
+        }
+        serde_json::from_str(&body).map_err(|err| Error::decode(err, &body))
+    }
+
+    // Traits of every token in `uris`, fetched `concurrency` at a time. Tokens
+    // whose metadata can't be fetched or read are logged and left out.
+    pub async fn fetch_traits(&self, uris: Vec<(i16, String)>) -> HashMap<i16, KongTraits> {
+        let permits = Arc::new(Semaphore::new(self.concurrency));
+        let tasks: Vec<_> = uris
+            .into_iter()
+            .map(|(id, uri)| {
+                let fetcher = self.clone();
+                let permits = permits.clone();
+                tokio::spawn(async move {
+                    let _permit = permits.acquire_owned().await;
+                    let traits = match fetcher.fetch(&uri).await {
+                        Ok(metadata) => metadata.traits(),
+                        Err(err) => Err(err),
+                    };
+                    (id, uri, traits)
+                })
+            })
+            .collect();
+        let mut fetched = HashMap::new();
+        for task in tasks {
+            match task.await {
+                Ok((id, _, Ok(traits))) => {
+                    fetched.insert(id, traits);
+                }
+                Ok((id, uri, Err(err))) => {
+                    warn!(token_id = id, uri = %uri, error = %err, "Couldnt load token metadata. Skipping token")
+                }
+                Err(err) => warn!(error = %err, "Metadata fetch panicked"),
+            }
+        }
+        info!(tokens = fetched.len(), "Fetched metadata");
+        fetched
+    }
+}
+
+// Tokens whose fetched traits differ from the ones on record, in id order.
+pub fn changed_tokens(
+    current: &HashMap<i16, KongData>,
+    fetched: &HashMap<i16, KongTraits>,
+) -> Vec<i16> {
+    let mut changed: Vec<i16> = fetched
+        .iter()
+        .filter(|(id, traits)| current.get(id).map(|d| &d.traits) != Some(*traits))
+        .map(|(id, _)| *id)
+        .collect();
+    changed.sort_unstable();
+    changed
+}
+
+// Writes traits in the layout of the checked-in `metadata.json`, keyed and
+// ordered by token id.
+pub fn write_metadata_json(path: &str, traits: &HashMap<i16, KongTraits>) -> Result<()> {
+    let ordered: BTreeMap<&i16, &KongTraits> = traits.iter().collect();
+    let json = serde_json::to_string_pretty(&ordered).map_err(Error::storage)?;
+    fs::write(path, json + "\n")?;
+    Ok(())
+}
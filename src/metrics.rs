@@ -0,0 +1,160 @@
+use once_cell::sync::Lazy;
+use prometheus::{
+    exponential_buckets, register_gauge, register_histogram, register_histogram_vec,
+    register_int_counter, register_int_counter_vec, register_int_gauge, register_int_gauge_vec,
+    Encoder, Gauge, Histogram, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
+    TextEncoder,
+};
+
+pub static OPENSEA_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "opensea_requests_total",
+        "OpenSea requests by endpoint and response status",
+        &["endpoint", "status"]
+    )
+    .unwrap()
+});
+pub static OPENSEA_REQUEST_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "opensea_request_duration_seconds",
+        "OpenSea request latency by endpoint",
+        &["endpoint"]
+    )
+    .unwrap()
+});
+pub static RPC_BATCH_SIZE: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "rpc_batch_size",
+        "Number of calls per JSON-RPC batch",
+        &["method"],
+        exponential_buckets(1.0, 4.0, 8).unwrap()
+    )
+    .unwrap()
+});
+pub static RPC_BATCH_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "rpc_batch_duration_seconds",
+        "JSON-RPC batch latency",
+        &["method"],
+        exponential_buckets(0.1, 2.0, 10).unwrap()
+    )
+    .unwrap()
+});
+pub static RPC_CALL_FAILURES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "rpc_call_failures_total",
+        "Contract calls that reverted or errored in an otherwise successful read",
+        &["method"]
+    )
+    .unwrap()
+});
+pub static RPC_ENDPOINT_FAILURES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "rpc_endpoint_failures_total",
+        "JSON-RPC batches that couldnt be delivered, by endpoint",
+        &["endpoint"]
+    )
+    .unwrap()
+});
+pub static RPC_ENDPOINT_HEALTHY: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "rpc_endpoint_healthy",
+        "0 while an RPC endpoint is benched after failing",
+        &["endpoint"]
+    )
+    .unwrap()
+});
+pub static TOKENS_UPDATED: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "tokens_updated_per_cycle",
+        "Tokens updated in the last cycle by job",
+        &["job"]
+    )
+    .unwrap()
+});
+pub static MONGO_WRITE_DURATION: Lazy<Histogram> = Lazy::new(|| {
+    register_histogram!(
+        "mongo_write_duration_seconds",
+        "Time taken to replace the formatted collection",
+        exponential_buckets(0.05, 2.0, 10).unwrap()
+    )
+    .unwrap()
+});
+pub static CACHE_SIZE: Lazy<IntGauge> =
+    Lazy::new(|| register_int_gauge!("cache_size_bytes", "Size of the cache file").unwrap());
+pub static FLOOR_PRICE: Lazy<Gauge> = Lazy::new(|| {
+    register_gauge!("floor_price_eth", "Lowest current listing price in ETH").unwrap()
+});
+pub static LISTED: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!("tokens_listed", "Number of tokens with a current listing").unwrap()
+});
+pub static OPENSEA_KEY_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "opensea_key_requests_total",
+        "OpenSea requests by API key and response status",
+        &["key", "status"]
+    )
+    .unwrap()
+});
+pub static OPENSEA_KEY_BENCHES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "opensea_key_benches_total",
+        "Times an API key was taken out of rotation, by reason",
+        &["key", "reason"]
+    )
+    .unwrap()
+});
+pub static OPENSEA_CIRCUIT_OPEN: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "opensea_circuit_open",
+        "1 while OpenSea requests are being short-circuited"
+    )
+    .unwrap()
+});
+pub static SCRAPER_ERRORS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "scraper_errors_total",
+        "Failed jobs by error kind",
+        &["job", "kind"]
+    )
+    .unwrap()
+});
+pub static INDEXED_BLOCK: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "indexed_block",
+        "Latest block indexed, and latest block whose data is final",
+        &["state"]
+    )
+    .unwrap()
+});
+pub static CHAIN_REORGS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!("chain_reorgs_total", "Reorgs seen while indexing").unwrap()
+});
+
+// Forces registration so every metric shows up on /metrics before its first sample.
+pub fn init() {
+    Lazy::force(&OPENSEA_REQUESTS);
+    Lazy::force(&OPENSEA_REQUEST_DURATION);
+    Lazy::force(&RPC_BATCH_SIZE);
+    Lazy::force(&RPC_BATCH_DURATION);
+    Lazy::force(&RPC_CALL_FAILURES);
+    Lazy::force(&RPC_ENDPOINT_FAILURES);
+    Lazy::force(&RPC_ENDPOINT_HEALTHY);
+    Lazy::force(&TOKENS_UPDATED);
+    Lazy::force(&MONGO_WRITE_DURATION);
+    Lazy::force(&CACHE_SIZE);
+    Lazy::force(&FLOOR_PRICE);
+    Lazy::force(&LISTED);
+    Lazy::force(&OPENSEA_KEY_REQUESTS);
+    Lazy::force(&OPENSEA_KEY_BENCHES);
+    Lazy::force(&OPENSEA_CIRCUIT_OPEN);
+    Lazy::force(&SCRAPER_ERRORS);
+    Lazy::force(&INDEXED_BLOCK);
+    Lazy::force(&CHAIN_REORGS);
+}
+
+pub fn gather() -> anyhow::Result<String> {
+    let mut buf = Vec::new();
+    TextEncoder::new().encode(&prometheus::gather(), &mut buf)?;
+    Ok(String::from_utf8(buf)?)
+}
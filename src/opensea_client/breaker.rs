@@ -0,0 +1,70 @@
+use crate::{
+    error::{Error, Result},
+    metrics::OPENSEA_CIRCUIT_OPEN,
+};
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tracing::warn;
+
+// Stops sending requests for `cooldown` once `threshold` requests in a row have
+// failed. After the cooldown one request is let through as a probe and the rest
+// are still turned away; a success closes the circuit again, another failure
+// reopens it. A probe that never reports back frees its slot after another
+// cooldown. A threshold of 0 disables it.
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    failures: AtomicU32,
+    open_until: Mutex<Option<Instant>>,
+}
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        CircuitBreaker {
+            threshold,
+            cooldown,
+            failures: AtomicU32::new(0),
+            open_until: Mutex::new(None),
+        }
+    }
+
+    pub fn check(&self) -> Result<()> {
+        let mut open_until = self.open_until.lock().unwrap();
+        match *open_until {
+            Some(until) if Instant::now() < until => Err(Error::CircuitOpen {
+                failures: self.failures.load(Ordering::Relaxed),
+            }),
+            // This caller is the probe. Everyone else waits for it to report.
+            Some(_) => {
+                *open_until = Some(Instant::now() + self.cooldown);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub fn record_success(&self) {
+        self.failures.store(0, Ordering::Relaxed);
+        if self.open_until.lock().unwrap().take().is_some() {
+            OPENSEA_CIRCUIT_OPEN.set(0);
+        }
+    }
+
+    pub fn record_failure(&self) {
+        let failures = self.failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.threshold == 0 || failures < self.threshold {
+            return;
+        }
+        warn!(
+            failures,
+            cooldown_secs = self.cooldown.as_secs_f64(),
+            "Opening OpenSea circuit"
+        );
+        *self.open_until.lock().unwrap() = Some(Instant::now() + self.cooldown);
+        OPENSEA_CIRCUIT_OPEN.set(1);
+    }
+}
@@ -1,5 +1,5 @@
 use crate::opensea_client::Request;
-use reqwest::RequestBuilder;
+use reqwest::{Client, RequestBuilder};
 use serde::Deserialize;
 use serde_aux::prelude::*;
 #[derive(Deserialize, Debug)]
@@ -15,6 +15,7 @@ pub struct Event {
     pub auction_type: Option<String>,
     pub created_date: Option<String>,
     pub starting_price: Option<String>,
+    pub order_hash: Option<String>,
 }
 #[derive(Deserialize, Debug)]
 pub struct EventsResponse {
@@ -42,12 +43,12 @@ impl EventsRequest {
         cursor: Option<String>,
     ) -> Self {
         EventsRequest {
-            asset_contract_address: asset_contract_address,
-            event_type: event_type,
-            auction_type: auction_type,
-            occurred_before: occurred_before,
-            occurred_after: occurred_after,
-            cursor: cursor,
+            asset_contract_address,
+            event_type,
+            auction_type,
+            occurred_before,
+            occurred_after,
+            cursor,
         }
     }
     pub fn set_asset_contract_address(&mut self, new_asset_contract_address: String) {
@@ -70,10 +71,13 @@ impl EventsRequest {
     }
 }
 impl Request for EventsRequest {
-    fn build_request(&self) -> RequestBuilder {
+    fn endpoint(&self) -> &'static str {
+        "events"
+    }
+    fn build_request(&self, client: &Client, base_url: &str) -> RequestBuilder {
         let mut query: Vec<(String, String)> = vec![(
             "asset_contract_address".to_string(),
-            (&self.asset_contract_address).to_string(),
+            self.asset_contract_address.to_string(),
         )];
         if let Some(elem) = &self.event_type {
             query.push(("event_type".to_string(), elem.to_string()));
@@ -90,8 +94,8 @@ impl Request for EventsRequest {
         if let Some(elem) = &self.cursor {
             query.push(("cursor".to_string(), elem.to_string()));
         };
-        reqwest::Client::new()
-            .get("https://api.opensea.io/api/v1/events")
+        client
+            .get(format!("{}/api/v1/events", base_url))
             .query(&query)
     }
 }
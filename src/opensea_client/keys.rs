@@ -0,0 +1,132 @@
+use crate::{
+    error::{Error, Result},
+    metrics::{OPENSEA_KEY_BENCHES, OPENSEA_KEY_REQUESTS},
+};
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::warn;
+
+struct KeyState {
+    value: String,
+    label: String,
+    // Earliest time the key may send again, either from throttling or a bench.
+    ready_at: Instant,
+    // Status OpenSea last rejected the key with. Cleared once the key is used
+    // again after sitting out its cooldown.
+    rejected: Option<u16>,
+}
+
+// Spreads requests over several API keys. Every key is throttled on its own,
+// so adding keys raises throughput, and keys that get rate limited or rejected
+// are benched while the others carry on.
+pub struct KeyPool {
+    throttle: Duration,
+    keys: Mutex<Vec<KeyState>>,
+    cursor: Mutex<usize>,
+}
+
+// A key reserved for one request, along with how long to wait before sending.
+pub struct Lease {
+    pub index: usize,
+    pub value: String,
+    pub label: String,
+    pub wait: Duration,
+}
+
+impl KeyPool {
+    pub fn new(keys: &[String], throttle: Duration) -> Self {
+        let now = Instant::now();
+        KeyPool {
+            throttle,
+            keys: Mutex::new(
+                keys.iter()
+                    .map(|k| KeyState {
+                        value: k.clone(),
+                        label: key_label(k),
+                        ready_at: now,
+                        rejected: None,
+                    })
+                    .collect(),
+            ),
+            cursor: Mutex::new(0),
+        }
+    }
+
+    // Reserves the key that can send soonest, preferring the next one in turn on ties.
+    // Rejected keys are skipped until their cooldown is over, rather than waited on.
+    pub fn acquire(&self) -> Result<Lease> {
+        let mut keys = self.keys.lock().unwrap();
+        let mut cursor = self.cursor.lock().unwrap();
+        let len = keys.len();
+        let now = Instant::now();
+        let index = (0..len)
+            .map(|offset| (*cursor + offset) % len)
+            .filter(|i| keys[*i].rejected.is_none() || keys[*i].ready_at <= now)
+            .min_by_key(|i| keys[*i].ready_at)
+            .ok_or(Error::Unauthorized {
+                status: keys.iter().find_map(|k| k.rejected).unwrap_or(401),
+            })?;
+        let key = &mut keys[index];
+        key.rejected = None;
+        let start = key.ready_at.max(now);
+        key.ready_at = start + self.throttle;
+        *cursor = (index + 1) % len;
+        Ok(Lease {
+            index,
+            value: key.value.clone(),
+            label: key.label.clone(),
+            wait: start - now,
+        })
+    }
+
+    pub fn record(&self, lease: &Lease, status: &str) {
+        OPENSEA_KEY_REQUESTS
+            .with_label_values(&[lease.label.as_str(), status])
+            .inc();
+    }
+
+    // Keeps a rate limited key out of rotation for `duration`.
+    pub fn bench(&self, lease: &Lease, duration: Duration) {
+        let mut keys = self.keys.lock().unwrap();
+        let key = &mut keys[lease.index];
+        key.ready_at = key.ready_at.max(Instant::now() + duration);
+        OPENSEA_KEY_BENCHES
+            .with_label_values(&[lease.label.as_str(), "rate_limited"])
+            .inc();
+    }
+
+    // Keeps a rejected key out of rotation for `cooldown`, since OpenSea and its
+    // CDN sometimes reject good keys for a while. Returns whether any key that
+    // hasn't been rejected is left to try instead.
+    pub fn reject(&self, lease: &Lease, status: u16, cooldown: Duration) -> bool {
+        let mut keys = self.keys.lock().unwrap();
+        let key = &mut keys[lease.index];
+        key.rejected = Some(status);
+        key.ready_at = key.ready_at.max(Instant::now() + cooldown);
+        warn!(
+            key = %lease.label,
+            status,
+            cooldown_secs = cooldown.as_secs(),
+            "API key rejected"
+        );
+        OPENSEA_KEY_BENCHES
+            .with_label_values(&[lease.label.as_str(), "rejected"])
+            .inc();
+        keys.iter().any(|k| k.rejected.is_none())
+    }
+}
+
+// Enough of the key to tell keys apart in logs and metrics without leaking it.
+fn key_label(key: &str) -> String {
+    let tail: String = key
+        .chars()
+        .rev()
+        .take(4)
+        .collect::<Vec<char>>()
+        .into_iter()
+        .rev()
+        .collect();
+    format!("...{}", tail)
+}
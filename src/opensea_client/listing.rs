@@ -1,14 +1,17 @@
 use crate::{
-    kong_data::{Marketplace, Sale, SaleType},
-    opensea_client::Request,
-    utils::wei_to_eth,
+    kong_data::{dedup_orders, Marketplace, Sale, SaleType},
+    opensea_client::{ProtocolData, Request},
+    payment::{parse_amount, AmountError, PaymentToken, Rates},
 };
 use core::fmt::Debug;
-use reqwest::RequestBuilder;
+use reqwest::{Client, RequestBuilder};
 use serde::{Deserialize, Serialize};
 use serde_aux::prelude::*;
+use tracing::warn;
+use web3::types::U256;
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct SeaportListing {
+    pub order_hash: Option<String>,
     pub created_date: String,
     pub closing_date: Option<String>,
     pub listing_time: u64,
@@ -17,6 +20,14 @@ pub struct SeaportListing {
     #[serde(deserialize_with = "deserialize_string_from_number")]
     pub side: String,
     pub order_type: Option<String>,
+    pub payment_token_contract: Option<PaymentTokenContract>,
+    pub protocol_data: Option<ProtocolData>,
+}
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct PaymentTokenContract {
+    pub address: String,
+    pub symbol: String,
+    pub decimals: u8,
 }
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct ListingsResponse {
@@ -36,58 +47,62 @@ price_eth: f64,
 price_usd: Option<f64>,
 platform: Marketplace,
 link: String, */
+impl SeaportListing {
+    pub fn to_sale(&self) -> Result<Sale, AmountError> {
+        let payment_token = match &self.payment_token_contract {
+            // OpenSea's symbol isn't trusted, only the address says what the token is.
+            Some(c) => PaymentToken::resolve(&c.address),
+            None => PaymentToken::eth(),
+        };
+        Ok(Sale {
+            order_hash: self.order_hash.clone(),
+            amount: parse_amount(&self.current_price)?,
+            payment_token,
+            created_timestamp: self.listing_time,
+            expiration_timestamp: self.expiration_time,
+            sale_type: match self.order_type.as_deref() {
+                Some("basic") | None => SaleType::BuyNow,
+                Some(_) => SaleType::Auction,
+            },
+            auction: match &self.protocol_data {
+                Some(data) => data
+                    .parameters
+                    .auction(self.order_type.as_deref().unwrap_or("basic"))?,
+                None => None,
+            },
+            price_wei: U256::zero(),
+            price_usd: None,
+            platform: Marketplace::OpenSea,
+        })
+    }
+}
 impl ListingsResponse {
-    pub fn format_listing(&self) -> Vec<Sale> {
+    pub fn format_listing(&self, rates: &Rates) -> Vec<Sale> {
+        // Seaport orders can show up in both lists.
         let mut list: Vec<Sale> = self
             .listings
-            .clone()
             .iter()
-            .map(|elem| Sale {
-                created_timestamp: elem.listing_time,
-                expiration_timestamp: elem.expiration_time,
-                sale_type: if let Some(typ) = &elem.order_type {
-                    match typ.as_str() {
-                        "basic" => SaleType::BuyNow,
-                        _ => SaleType::Auction,
-                    }
-                } else {
-                    SaleType::BuyNow
-                },
-                price_eth: wei_to_eth(elem.current_price.clone()),
-                price_usd: None,
-                platform: Marketplace::OpenSea,
+            .chain(self.seaport_listings.iter())
+            .filter_map(|listing| match listing.to_sale() {
+                Ok(sale) => sale.priced(rates),
+                Err(err) => {
+                    warn!(order_hash = ?listing.order_hash, error = %err, "Couldnt parse listing price. Skipping listing");
+                    None
+                }
             })
             .collect();
-        let mut sp_list = self
-            .seaport_listings
-            .clone()
-            .iter()
-            .map(|elem| Sale {
-                created_timestamp: elem.listing_time,
-                expiration_timestamp: elem.expiration_time,
-                sale_type: if let Some(typ) = &elem.order_type {
-                    match typ.as_str() {
-                        "basic" => SaleType::BuyNow,
-                        _ => SaleType::Auction,
-                    }
-                } else {
-                    SaleType::BuyNow
-                },
-                price_eth: wei_to_eth(elem.current_price.clone()),
-                price_usd: None,
-                platform: Marketplace::OpenSea,
-            })
-            .collect();
-        list.append(&mut sp_list);
+        dedup_orders(&mut list);
+        // Cheapest first, like the v2 listings.
+        list.sort_by_key(|s| s.price_wei);
         list
     }
 }
 impl ListingsRequest {
     pub fn new(asset_contract_address: String, token_id: i16, limit: Option<i8>) -> Self {
         ListingsRequest {
-            asset_contract_address: asset_contract_address,
-            token_id: token_id,
-            limit: limit,
+            asset_contract_address,
+            token_id,
+            limit,
         }
     }
     pub fn set_token_id(&mut self, new_token_id: i16) {
@@ -95,12 +110,14 @@ impl ListingsRequest {
     }
 }
 impl Request for ListingsRequest {
-    fn build_request(&self) -> RequestBuilder {
+    fn endpoint(&self) -> &'static str {
+        "listings"
+    }
+    fn build_request(&self, client: &Client, base_url: &str) -> RequestBuilder {
         let query_str = format!(
-            "https://api.opensea.io/api/v1/asset/{}/{}/listings",
-            self.asset_contract_address, self.token_id
+            "{}/api/v1/asset/{}/{}/listings",
+            base_url, self.asset_contract_address, self.token_id
         );
-        let client = reqwest::Client::new();
         if let Some(l) = self.limit {
             client.get(query_str).query(&[("limit", l)])
         } else {
@@ -1,4 +1,12 @@
+pub mod breaker;
 pub mod event;
+pub mod keys;
 pub mod listing;
+#[allow(clippy::module_inception)]
 pub mod opensea_client;
-pub use self::{event::*, listing::*, opensea_client::*};
+pub mod recorder;
+pub mod seaport;
+pub mod v2;
+pub use self::{
+    breaker::*, event::*, keys::*, listing::*, opensea_client::*, recorder::*, seaport::*, v2::*,
+};
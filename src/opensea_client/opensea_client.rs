@@ -1,65 +1,295 @@
+use crate::{
+    error::{Error, Result},
+    metrics::{OPENSEA_REQUESTS, OPENSEA_REQUEST_DURATION},
+    opensea_client::{
+        breaker::CircuitBreaker,
+        keys::{KeyPool, Lease},
+        recorder::{Exchange, HttpMode},
+    },
+};
 use anyhow::anyhow;
 use async_recursion::async_recursion;
 use core::fmt::Debug;
-use reqwest::{header::HeaderMap, RequestBuilder};
+use reqwest::{header::HeaderMap, Client, Proxy, RequestBuilder};
 use serde::de::DeserializeOwned;
-use std::{thread::sleep, time::Duration};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::time::sleep;
+use tracing::warn;
+
+// How hard to try before giving up on a request.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub timeout: Duration,
+    pub max_retries: u8,
+    pub breaker_threshold: u32,
+    pub breaker_cooldown: Duration,
+    // How long a key OpenSea answered 401 or 403 for sits out.
+    pub key_cooldown: Duration,
+}
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            timeout: Duration::from_secs(30),
+            max_retries: 5,
+            breaker_threshold: 10,
+            breaker_cooldown: Duration::from_secs(60),
+            key_cooldown: Duration::from_secs(600),
+        }
+    }
+}
+
+// How the underlying HTTP client connects. Shared by every request the
+// OpenseaClient makes, so connections and TLS sessions are reused. HTTPS
+// connections offer HTTP/2 through ALPN and fall back to HTTP/1.1 when the
+// server doesn't take it.
+#[derive(Debug, Clone)]
+pub struct HttpOptions {
+    pub user_agent: String,
+    pub proxy: Option<String>,
+    pub connect_timeout: Duration,
+    pub pool_idle_timeout: Duration,
+}
+impl Default for HttpOptions {
+    fn default() -> Self {
+        HttpOptions {
+            user_agent: format!("kong-scraper/{}", env!("CARGO_PKG_VERSION")),
+            proxy: None,
+            connect_timeout: Duration::from_secs(10),
+            pool_idle_timeout: Duration::from_secs(90),
+        }
+    }
+}
+impl HttpOptions {
+    pub fn build(&self) -> reqwest::Result<Client> {
+        let mut builder = Client::builder()
+            .user_agent(self.user_agent.as_str())
+            .connect_timeout(self.connect_timeout)
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .http2_adaptive_window(true)
+            .gzip(true);
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(Proxy::all(proxy.as_str())?);
+        }
+        builder.build()
+    }
+}
+
+#[derive(Clone)]
 pub struct OpenseaClient {
+    http: Client,
     headers: HeaderMap,
+    base_url: String,
+    keys: Arc<KeyPool>,
+    backoff: Duration,
+    mode: Arc<HttpMode>,
+    policy: RetryPolicy,
+    breaker: Arc<CircuitBreaker>,
 }
 impl OpenseaClient {
-    pub fn new(k: &str) -> Self {
+    // `throttle` is the minimum gap between requests made with the same key,
+    // `backoff` is the unit of the linear wait after each 429 or retried failure.
+    pub fn new(keys: &[String], base_url: &str, throttle: Duration, backoff: Duration) -> Self {
         let mut h = HeaderMap::new();
         h.insert("Accept", "application/json".parse().unwrap());
-        h.insert("X-API-KEY", k.parse().unwrap());
-        OpenseaClient { headers: h }
+        let policy = RetryPolicy::default();
+        OpenseaClient {
+            http: HttpOptions::default()
+                .build()
+                .expect("couldnt build default http client"),
+            headers: h,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            keys: Arc::new(KeyPool::new(keys, throttle)),
+            backoff,
+            mode: Arc::new(HttpMode::Live),
+            breaker: Arc::new(CircuitBreaker::new(
+                policy.breaker_threshold,
+                policy.breaker_cooldown,
+            )),
+            policy,
+        }
+    }
+    pub fn set_http_mode(&mut self, new_mode: HttpMode) {
+        self.mode = Arc::new(new_mode)
+    }
+    pub fn set_http_options(&mut self, options: &HttpOptions) -> Result<()> {
+        self.http = options.build()?;
+        Ok(())
+    }
+    pub fn set_retry_policy(&mut self, new_policy: RetryPolicy) {
+        self.breaker = Arc::new(CircuitBreaker::new(
+            new_policy.breaker_threshold,
+            new_policy.breaker_cooldown,
+        ));
+        self.policy = new_policy
+    }
+
+    // Succeeds if OpenSea answers at all with anything other than a server error.
+    // A replay has no live OpenSea behind it, so there is nothing to check.
+    pub async fn ping(&self) -> anyhow::Result<()> {
+        if matches!(*self.mode, HttpMode::Replay(_)) {
+            return Ok(());
+        }
+        let lease = self.keys.acquire()?;
+        if !lease.wait.is_zero() {
+            sleep(lease.wait).await;
+        }
+        let sent = self
+            .http
+            .get(format!("{}/api/v1/", &self.base_url))
+            .headers(self.headers.clone())
+            .header("X-API-KEY", lease.value.as_str())
+            .timeout(self.policy.timeout)
+            .send()
+            .await;
+        let status = match &sent {
+            Ok(res) => res.status().as_u16().to_string(),
+            Err(_) => String::from("error"),
+        };
+        self.keys.record(&lease, status.as_str());
+        let res = sent?;
+        if res.status().is_server_error() {
+            Err(anyhow!("OpenSea returned {}", res.status()))
+        } else {
+            Ok(())
+        }
     }
 
     pub async fn request<T: Request + Sync + Debug, U: DeserializeOwned + Debug>(
         &self,
         req: &T,
-    ) -> anyhow::Result<U> {
-        Ok(self.try_request::<T, U>(req, None).await?)
+    ) -> Result<U> {
+        self.try_request::<T, U>(req, None).await
     }
+    #[allow(clippy::multiple_bound_locations)]
     #[async_recursion]
     async fn try_request<T: Request + Sync + Debug, U: DeserializeOwned + Debug>(
         &self,
         req: &T,
         nonce: Option<u8>,
-    ) -> anyhow::Result<U> {
-        let n: u8 = if let Some(non) = nonce { non } else { 1_u8 };
-        let r_built: RequestBuilder = req.build_request().headers(self.headers.clone());
-        if let Ok(res) = r_built.send().await {
-            sleep(Duration::new(0, 300_000_000));
-            match res.status().into() {
-                200 => {
-                    let stuff: U = res.json().await?;
-                    Ok(stuff)
+    ) -> Result<U> {
+        let n: u8 = nonce.unwrap_or(1_u8);
+        self.breaker.check()?;
+        let replaying = matches!(*self.mode, HttpMode::Replay(_));
+        let lease: Lease = self.keys.acquire()?;
+        // Even a zero sleep waits for the next timer tick.
+        if !replaying && !lease.wait.is_zero() {
+            sleep(lease.wait).await;
+        }
+        let r_built: RequestBuilder = req
+            .build_request(&self.http, &self.base_url)
+            .headers(self.headers.clone())
+            .header("X-API-KEY", lease.value.as_str())
+            .timeout(self.policy.timeout);
+        let start = Instant::now();
+        let sent = self.send(r_built).await;
+        OPENSEA_REQUEST_DURATION
+            .with_label_values(&[req.endpoint()])
+            .observe(start.elapsed().as_secs_f64());
+        let status = match &sent {
+            Ok((code, _)) => code.to_string(),
+            Err(_) => String::from("error"),
+        };
+        OPENSEA_REQUESTS
+            .with_label_values(&[req.endpoint(), status.as_str()])
+            .inc();
+        self.keys.record(&lease, status.as_str());
+        let (code, body) = match sent {
+            Ok(res) => res,
+            // A missing recording won't show up by asking again.
+            Err(err) if replaying => return Err(err),
+            Err(err) => {
+                self.breaker.record_failure();
+                return self.retry(req, n, err).await;
+            }
+        };
+        if code >= 500 {
+            self.breaker.record_failure();
+        } else {
+            self.breaker.record_success();
+        }
+        match code {
+            200 => serde_json::from_str(&body).map_err(|err| Error::decode(err, &body)),
+            401 | 403 => {
+                if self.keys.reject(&lease, code, self.policy.key_cooldown) {
+                    self.try_request(req, Some(n)).await
+                } else {
+                    Err(Error::Unauthorized { status: code })
                 }
-                429 => {
-                    if n >= 20 {
-                        Err(anyhow!("Too many tries for request"))
-                    } else {
-                        let wait = n * 3;
-                        println!(
-                            "Too many requests.\nNonce: {}\nWaiting {} seconds",
-                            &n, &wait
-                        );
-                        sleep(Duration::new(wait.into(), 0));
-                        self.try_request(req, Some(n + 1)).await
-                    }
+            }
+            429 => {
+                if n >= 20 {
+                    Err(Error::RateLimited { attempts: n })
+                } else {
+                    // Other keys keep going while this one sits out the backoff.
+                    let wait = self.backoff * n.into();
+                    warn!(
+                        nonce = n,
+                        key = %lease.label,
+                        wait_secs = wait.as_secs_f64(),
+                        "Too many requests"
+                    );
+                    self.keys.bench(&lease, wait);
+                    self.try_request(req, Some(n + 1)).await
                 }
-                all_others => Err(anyhow!(
-                    "Unexpected response. Code: {}\nRequest: {:#?}",
-                    all_others,
-                    req
-                )),
             }
-        } else {
-            Err(anyhow!("Error sending request."))
+            500..=599 => self.retry(req, n, Error::http_status(code, &body)).await,
+            all_others => Err(Error::http_status(all_others, &body)),
+        }
+    }
+    // Tries again after a linear backoff, or gives up with `err` once the
+    // policy's retries are used up.
+    async fn retry<T: Request + Sync + Debug, U: DeserializeOwned + Debug>(
+        &self,
+        req: &T,
+        n: u8,
+        err: Error,
+    ) -> Result<U> {
+        if n > self.policy.max_retries {
+            return Err(err);
+        }
+        let wait = self.backoff * n.into();
+        warn!(
+            nonce = n,
+            wait_secs = wait.as_secs_f64(),
+            error = %err,
+            "Retrying request"
+        );
+        sleep(wait).await;
+        self.try_request(req, Some(n + 1)).await
+    }
+
+    // Status and body of the response, read from the recording when replaying.
+    async fn send(&self, r_built: RequestBuilder) -> Result<(u16, String)> {
+        let built = r_built.build()?;
+        let method = built.method().to_string();
+        let url = built.url().to_string();
+        let path = url.strip_prefix(&self.base_url).unwrap_or(&url).to_string();
+        if let HttpMode::Replay(replayer) = &*self.mode {
+            let exchange = replayer
+                .next(&method, &path)
+                .map_err(|err| Error::Transport(err.into()))?;
+            return Ok((exchange.status, exchange.body));
+        }
+        let res = self.http.execute(built).await?;
+        let status = res.status().as_u16();
+        let body = res.text().await?;
+        if let HttpMode::Record(recorder) = &*self.mode {
+            recorder
+                .record(&Exchange {
+                    method,
+                    path,
+                    status,
+                    body: body.clone(),
+                })
+                .map_err(Error::storage)?;
         }
+        Ok((status, body))
     }
 }
 pub trait Request {
-    fn build_request(&self) -> RequestBuilder;
+    fn build_request(&self, client: &Client, base_url: &str) -> RequestBuilder;
+    fn endpoint(&self) -> &'static str;
 }
@@ -0,0 +1,81 @@
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    sync::Mutex,
+};
+
+// One request/response pair. `path` is the url without the client's base url,
+// so a session recorded against OpenSea replays against any base url.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Exchange {
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    pub body: String,
+}
+impl Exchange {
+    fn key(&self) -> String {
+        format!("{} {}", self.method, self.path)
+    }
+}
+
+pub enum HttpMode {
+    Live,
+    Record(Recorder),
+    Replay(Replayer),
+}
+
+// Appends every exchange to a JSON lines file as soon as it completes.
+pub struct Recorder {
+    writer: Mutex<BufWriter<File>>,
+}
+impl Recorder {
+    pub fn new(path: &str) -> anyhow::Result<Self> {
+        Ok(Recorder {
+            writer: Mutex::new(BufWriter::new(File::create(path)?)),
+        })
+    }
+    pub fn record(&self, exchange: &Exchange) -> anyhow::Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        serde_json::to_writer(&mut *writer, exchange)?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+// Serves recorded exchanges back in the order they were recorded, per request.
+pub struct Replayer {
+    exchanges: Mutex<HashMap<String, VecDeque<Exchange>>>,
+}
+impl Replayer {
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let mut exchanges: HashMap<String, VecDeque<Exchange>> = HashMap::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let exchange: Exchange = serde_json::from_str(&line)?;
+            exchanges
+                .entry(exchange.key())
+                .or_default()
+                .push_back(exchange);
+        }
+        Ok(Replayer {
+            exchanges: Mutex::new(exchanges),
+        })
+    }
+    pub fn next(&self, method: &str, path: &str) -> anyhow::Result<Exchange> {
+        let key = format!("{} {}", method, path);
+        self.exchanges
+            .lock()
+            .unwrap()
+            .get_mut(&key)
+            .and_then(|queue| queue.pop_front())
+            .ok_or_else(|| anyhow!("No recorded response left for {}", key))
+    }
+}
@@ -0,0 +1,178 @@
+use crate::{
+    kong_data::{Auction, AuctionKind, Marketplace, Sale, SaleType},
+    payment::{parse_amount, AmountError, PaymentToken, Rates},
+};
+use serde::{Deserialize, Serialize};
+use serde_aux::prelude::*;
+use tracing::warn;
+use web3::types::U256;
+
+// Seaport order types as returned by the v2 API. Field names follow the
+// Seaport structs so orders can be handed back to the contract untouched.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OfferItem {
+    pub item_type: u8,
+    pub token: String,
+    pub identifier_or_criteria: String,
+    pub start_amount: String,
+    pub end_amount: String,
+}
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ConsiderationItem {
+    pub item_type: u8,
+    pub token: String,
+    pub identifier_or_criteria: String,
+    pub start_amount: String,
+    pub end_amount: String,
+    pub recipient: String,
+}
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderParameters {
+    pub offerer: String,
+    pub offer: Vec<OfferItem>,
+    pub consideration: Vec<ConsiderationItem>,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub start_time: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub end_time: u64,
+    pub order_type: u8,
+    pub zone: String,
+    pub zone_hash: String,
+    pub salt: String,
+    pub conduit_key: String,
+    pub total_original_consideration_items: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub counter: u64,
+}
+impl OrderParameters {
+    // Price schedule of an auction listing, summed over the payment items.
+    // `order_type` is OpenSea's name for the listing; orders whose amounts move
+    // are Dutch auctions whatever they're called.
+    pub fn auction(&self, order_type: &str) -> Result<Option<Auction>, AmountError> {
+        let (mut start_amount, mut end_amount) = (U256::zero(), U256::zero());
+        for item in self.consideration.iter().filter(|item| item.item_type <= 1) {
+            start_amount = start_amount
+                .checked_add(parse_amount(&item.start_amount)?)
+                .ok_or(AmountError::Overflow)?;
+            end_amount = end_amount
+                .checked_add(parse_amount(&item.end_amount)?)
+                .ok_or(AmountError::Overflow)?;
+        }
+        let kind = match order_type {
+            "english" => AuctionKind::English,
+            "dutch" => AuctionKind::Dutch,
+            _ if start_amount != end_amount => AuctionKind::Dutch,
+            _ => return Ok(None),
+        };
+        Ok(Some(Auction {
+            kind,
+            start_amount,
+            end_amount,
+            start_time: self.start_time,
+            end_time: self.end_time,
+        }))
+    }
+}
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ProtocolData {
+    pub parameters: OrderParameters,
+    pub signature: Option<String>,
+}
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Fee {
+    pub account: Account,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub basis_points: u32,
+}
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Account {
+    pub address: String,
+}
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Order {
+    pub created_date: String,
+    pub closing_date: Option<String>,
+    pub listing_time: u64,
+    pub expiration_time: Option<u64>,
+    pub order_hash: String,
+    pub protocol_data: ProtocolData,
+    pub protocol_address: String,
+    pub current_price: String,
+    pub maker: Account,
+    pub maker_fees: Vec<Fee>,
+    pub taker_fees: Vec<Fee>,
+    pub side: String,
+    pub order_type: String,
+    pub cancelled: bool,
+    pub finalized: bool,
+    pub marked_invalid: bool,
+}
+impl Order {
+    // Consideration items paid to anyone but the offerer, i.e. marketplace and
+    // creator fees.
+    pub fn fee_items(&self) -> Vec<&ConsiderationItem> {
+        let params = &self.protocol_data.parameters;
+        params
+            .consideration
+            .iter()
+            .filter(|item| !item.recipient.eq_ignore_ascii_case(&params.offerer))
+            .collect()
+    }
+    // Listings are paid for in the token of their consideration items.
+    pub fn payment_token(&self) -> PaymentToken {
+        payment_token_of(&self.protocol_data.parameters)
+    }
+    pub fn is_active(&self) -> bool {
+        !(self.cancelled || self.finalized || self.marked_invalid)
+    }
+    pub fn to_sale(&self) -> Result<Sale, AmountError> {
+        Ok(Sale {
+            order_hash: Some(self.order_hash.clone()),
+            amount: parse_amount(&self.current_price)?,
+            payment_token: self.payment_token(),
+            created_timestamp: self.listing_time,
+            expiration_timestamp: self.expiration_time,
+            sale_type: match self.order_type.as_str() {
+                "basic" => SaleType::BuyNow,
+                _ => SaleType::Auction,
+            },
+            auction: self.protocol_data.parameters.auction(&self.order_type)?,
+            price_wei: U256::zero(),
+            price_usd: None,
+            platform: Marketplace::OpenSea,
+        })
+    }
+}
+pub fn payment_token_of(params: &OrderParameters) -> PaymentToken {
+    match params.consideration.first() {
+        Some(item) => PaymentToken::resolve(&item.token),
+        None => PaymentToken::eth(),
+    }
+}
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct OrdersResponse {
+    pub next: Option<String>,
+    pub previous: Option<String>,
+    pub orders: Vec<Order>,
+}
+impl OrdersResponse {
+    pub fn format_listing(&self, rates: &Rates) -> Vec<Sale> {
+        let mut list: Vec<Sale> = self
+            .orders
+            .iter()
+            .filter(|order| order.is_active())
+            .filter_map(|order| match order.to_sale() {
+                Ok(sale) => sale.priced(rates),
+                Err(err) => {
+                    warn!(order_hash = %order.order_hash, error = %err, "Couldnt parse listing price. Skipping listing");
+                    None
+                }
+            })
+            .collect();
+        list.sort_by_key(|s| s.price_wei);
+        list
+    }
+}
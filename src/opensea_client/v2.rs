@@ -0,0 +1,298 @@
+use crate::{
+    kong_data::{Marketplace, Sale, SaleType},
+    opensea_client::{payment_token_of, ProtocolData, Request},
+    payment::{parse_amount, AmountError, PaymentToken},
+};
+use anyhow::anyhow;
+use reqwest::{Client, RequestBuilder};
+use serde::Deserialize;
+use std::str::FromStr;
+use web3::types::U256;
+
+// Which OpenSea API the price update talks to. v1 is deprecated but stays the
+// default until v2 has been running for a while.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiVersion {
+    V1,
+    V2,
+}
+impl FromStr for ApiVersion {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_lowercase().as_str() {
+            "v1" | "1" => Ok(ApiVersion::V1),
+            "v2" | "2" => Ok(ApiVersion::V2),
+            other => Err(anyhow!("Unknown OpenSea API version: {}", other)),
+        }
+    }
+}
+
+// How the price update finds listings. `Incremental` asks for the listings of
+// each token that had an event since the last update, `Bulk` pages through every
+// active listing in the collection and rebuilds all tokens in one pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PriceStrategy {
+    Incremental,
+    Bulk,
+}
+impl FromStr for PriceStrategy {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_lowercase().as_str() {
+            "incremental" => Ok(PriceStrategy::Incremental),
+            "bulk" => Ok(PriceStrategy::Bulk),
+            other => Err(anyhow!("Unknown price strategy: {}", other)),
+        }
+    }
+}
+
+// The v2 order endpoints are per chain and protocol. Kongz only live here.
+const ORDERS_PATH: &str = "/api/v2/orders/ethereum/seaport";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderSide {
+    Listings,
+    Offers,
+}
+impl OrderSide {
+    fn as_str(&self) -> &'static str {
+        match self {
+            OrderSide::Listings => "listings",
+            OrderSide::Offers => "offers",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct OrdersRequest {
+    pub side: OrderSide,
+    pub asset_contract_address: String,
+    pub token_ids: Vec<i16>,
+    pub limit: Option<u8>,
+    pub cursor: Option<String>,
+}
+impl OrdersRequest {
+    pub fn new(side: OrderSide, asset_contract_address: String, token_ids: Vec<i16>) -> Self {
+        OrdersRequest {
+            side,
+            asset_contract_address,
+            token_ids,
+            limit: None,
+            cursor: None,
+        }
+    }
+    pub fn set_token_ids(&mut self, new_token_ids: Vec<i16>) {
+        self.token_ids = new_token_ids
+    }
+    pub fn set_limit(&mut self, new_limit: u8) {
+        self.limit = Some(new_limit)
+    }
+    pub fn set_cursor(&mut self, new_cursor: Option<String>) {
+        self.cursor = new_cursor
+    }
+}
+impl Request for OrdersRequest {
+    fn endpoint(&self) -> &'static str {
+        match self.side {
+            OrderSide::Listings => "v2_listings",
+            OrderSide::Offers => "v2_offers",
+        }
+    }
+    fn build_request(&self, client: &Client, base_url: &str) -> RequestBuilder {
+        let mut query: Vec<(&str, String)> = vec![(
+            "asset_contract_address",
+            self.asset_contract_address.to_string(),
+        )];
+        for id in &self.token_ids {
+            query.push(("token_ids", id.to_string()));
+        }
+        if let Some(l) = self.limit {
+            query.push(("limit", l.to_string()));
+        }
+        if let Some(c) = &self.cursor {
+            query.push(("cursor", c.to_string()));
+        }
+        client
+            .get(format!(
+                "{}{}/{}",
+                base_url,
+                ORDERS_PATH,
+                self.side.as_str()
+            ))
+            .query(&query)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Nft {
+    pub identifier: String,
+    pub contract: String,
+}
+#[derive(Deserialize, Debug)]
+pub struct V2Event {
+    pub event_type: String,
+    pub order_hash: Option<String>,
+    pub event_timestamp: u64,
+    // Sales and transfers carry `nft`, orders carry `asset`.
+    pub nft: Option<Nft>,
+    pub asset: Option<Nft>,
+}
+impl V2Event {
+    pub fn token_id(&self) -> Option<i16> {
+        self.nft
+            .as_ref()
+            .or(self.asset.as_ref())
+            .and_then(|nft| nft.identifier.parse().ok())
+    }
+}
+#[derive(Deserialize, Debug)]
+pub struct V2EventsResponse {
+    pub next: Option<String>,
+    pub asset_events: Vec<V2Event>,
+}
+
+#[derive(Debug)]
+pub struct V2EventsRequest {
+    pub collection_slug: String,
+    pub event_type: String,
+    pub after: Option<u64>,
+    pub before: Option<u64>,
+    pub next: Option<String>,
+}
+impl V2EventsRequest {
+    pub fn new(collection_slug: String, event_type: String, after: Option<u64>) -> Self {
+        V2EventsRequest {
+            collection_slug,
+            event_type,
+            after,
+            before: None,
+            next: None,
+        }
+    }
+    pub fn set_event_type(&mut self, new_event_type: String) {
+        self.event_type = new_event_type
+    }
+    pub fn set_cursor(&mut self, new_cursor: Option<String>) {
+        self.next = new_cursor
+    }
+}
+impl Request for V2EventsRequest {
+    fn endpoint(&self) -> &'static str {
+        "v2_events"
+    }
+    fn build_request(&self, client: &Client, base_url: &str) -> RequestBuilder {
+        let mut query: Vec<(&str, String)> = vec![("event_type", self.event_type.to_string())];
+        if let Some(a) = self.after {
+            query.push(("after", a.to_string()));
+        }
+        if let Some(b) = self.before {
+            query.push(("before", b.to_string()));
+        }
+        if let Some(n) = &self.next {
+            query.push(("next", n.to_string()));
+        }
+        client
+            .get(format!(
+                "{}/api/v2/events/collection/{}",
+                base_url, self.collection_slug
+            ))
+            .query(&query)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Amount {
+    pub currency: String,
+    pub decimals: u8,
+    pub value: String,
+}
+#[derive(Deserialize, Debug)]
+pub struct ListingPrice {
+    pub current: Amount,
+}
+#[derive(Deserialize, Debug)]
+pub struct CollectionListing {
+    pub order_hash: String,
+    #[serde(rename = "type")]
+    pub listing_type: String,
+    pub price: ListingPrice,
+    pub protocol_data: ProtocolData,
+    pub protocol_address: String,
+}
+impl CollectionListing {
+    // The token being sold, read from the first offer item of the order.
+    pub fn token_id(&self) -> Option<i16> {
+        self.protocol_data
+            .parameters
+            .offer
+            .first()
+            .and_then(|item| item.identifier_or_criteria.parse().ok())
+    }
+    // Read from the order's consideration, not the currency OpenSea reports.
+    pub fn payment_token(&self) -> PaymentToken {
+        payment_token_of(&self.protocol_data.parameters)
+    }
+    pub fn to_sale(&self) -> Result<Sale, AmountError> {
+        let params = &self.protocol_data.parameters;
+        Ok(Sale {
+            order_hash: Some(self.order_hash.clone()),
+            amount: parse_amount(&self.price.current.value)?,
+            payment_token: self.payment_token(),
+            created_timestamp: params.start_time,
+            expiration_timestamp: Some(params.end_time),
+            sale_type: match self.listing_type.as_str() {
+                "basic" => SaleType::BuyNow,
+                _ => SaleType::Auction,
+            },
+            auction: params.auction(&self.listing_type)?,
+            price_wei: U256::zero(),
+            price_usd: None,
+            platform: Marketplace::OpenSea,
+        })
+    }
+}
+#[derive(Deserialize, Debug)]
+pub struct CollectionListingsResponse {
+    pub listings: Vec<CollectionListing>,
+    pub next: Option<String>,
+}
+
+// Every active listing in the collection, 100 per page.
+#[derive(Debug)]
+pub struct CollectionListingsRequest {
+    pub collection_slug: String,
+    pub limit: Option<u8>,
+    pub next: Option<String>,
+}
+impl CollectionListingsRequest {
+    pub fn new(collection_slug: String) -> Self {
+        CollectionListingsRequest {
+            collection_slug,
+            limit: Some(100),
+            next: None,
+        }
+    }
+    pub fn set_cursor(&mut self, new_cursor: Option<String>) {
+        self.next = new_cursor
+    }
+}
+impl Request for CollectionListingsRequest {
+    fn endpoint(&self) -> &'static str {
+        "v2_collection_listings"
+    }
+    fn build_request(&self, client: &Client, base_url: &str) -> RequestBuilder {
+        let mut query: Vec<(&str, String)> = Vec::new();
+        if let Some(l) = self.limit {
+            query.push(("limit", l.to_string()));
+        }
+        if let Some(n) = &self.next {
+            query.push(("next", n.to_string()));
+        }
+        client
+            .get(format!(
+                "{}/api/v2/listings/collection/{}/all",
+                base_url, self.collection_slug
+            ))
+            .query(&query)
+    }
+}
@@ -0,0 +1,266 @@
+use crate::{
+    config::Config,
+    error::{Error, Result},
+    indexer::SaleRecord,
+    kong_data::Sale,
+    payment::{PaymentToken, Rates},
+};
+use async_trait::async_trait;
+use ethabi::Token;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    ops::Bound,
+    sync::{Arc, Mutex},
+};
+use tracing::warn;
+use web3::{
+    transports::Http,
+    types::{Address, CallRequest, U256},
+    Web3,
+};
+
+// Chainlink proxy round ids are `phase << 64 | aggregator round`.
+const PHASE_SHIFT: u32 = 64;
+
+#[async_trait]
+pub trait PriceOracle: Send + Sync {
+    // Latest ETH/USD price.
+    async fn eth_usd(&self) -> Result<f64>;
+    // ETH/USD price in effect at `timestamp` (unix seconds).
+    async fn eth_usd_at(&self, timestamp: u64) -> Result<f64>;
+    // Latest USD price of a payment token, or None if there's no source for it.
+    async fn token_usd(&self, token: &PaymentToken) -> Result<Option<f64>> {
+        if token.is_eth() {
+            Ok(Some(self.eth_usd().await?))
+        } else if token.is_stablecoin() {
+            Ok(Some(1.0))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+// Always answers with the same price. For tests and running without a node.
+pub struct FixedPrice(pub f64);
+#[async_trait]
+impl PriceOracle for FixedPrice {
+    async fn eth_usd(&self) -> Result<f64> {
+        Ok(self.0)
+    }
+    async fn eth_usd_at(&self, _timestamp: u64) -> Result<f64> {
+        Ok(self.0)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Round {
+    id: u128,
+    price: f64,
+    updated_at: u64,
+}
+
+// Reads Chainlink aggregator proxies over JSON-RPC. `feed` is ETH/USD, other
+// tokens use the feed listed for them in `payment`.
+pub struct Chainlink {
+    web3: Web3<Http>,
+    feed: Address,
+    abi: ethabi::Contract,
+    decimals: Mutex<HashMap<Address, u8>>,
+    // ETH/USD rounds seen while searching history, keyed by update time.
+    rounds: Mutex<BTreeMap<u64, Round>>,
+}
+impl Chainlink {
+    pub fn new(node_url: &str, feed: &str) -> anyhow::Result<Self> {
+        Ok(Chainlink {
+            web3: Web3::new(Http::new(node_url)?),
+            feed: parse_address(feed)?,
+            abi: ethabi::Contract::load(File::open("src/utils/chainlink_aggregator_abi.json")?)?,
+            decimals: Mutex::new(HashMap::new()),
+            rounds: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    async fn call(&self, feed: Address, name: &str, args: &[Token]) -> Result<Vec<Token>> {
+        let func = self.abi.function(name)?;
+        let req = CallRequest::builder()
+            .to(feed)
+            .data(func.encode_input(args)?.into())
+            .build();
+        let out = self.web3.eth().call(req, None).await?;
+        Ok(func.decode_output(&out.0)?)
+    }
+
+    async fn decimals(&self, feed: Address) -> Result<u8> {
+        if let Some(d) = self.decimals.lock().unwrap().get(&feed) {
+            return Ok(*d);
+        }
+        let d = match self.call(feed, "decimals", &[]).await?.first() {
+            Some(Token::Uint(d)) => d.low_u32() as u8,
+            _ => return Err(ethabi::Error::InvalidData.into()),
+        };
+        self.decimals.lock().unwrap().insert(feed, d);
+        Ok(d)
+    }
+
+    async fn round(&self, feed: Address, name: &str, args: &[Token]) -> Result<Round> {
+        let decimals = self.decimals(feed).await?;
+        match self.call(feed, name, args).await?.as_slice() {
+            [Token::Uint(id), Token::Int(answer), _, Token::Uint(updated_at), _] => {
+                // A feed that answers zero or less is broken, not a price.
+                if answer.is_zero() || answer.bit(255) {
+                    return Err(web3::Error::InvalidResponse(format!(
+                        "Feed {:?} answered a non-positive price",
+                        feed
+                    ))
+                    .into());
+                }
+                Ok(Round {
+                    id: id.low_u128(),
+                    price: answer.low_u128() as f64 / 10_f64.powi(decimals.into()),
+                    updated_at: updated_at.low_u64(),
+                })
+            }
+            _ => Err(ethabi::Error::InvalidData.into()),
+        }
+    }
+
+    async fn round_at(&self, phase: u128, aggregator_round: u128) -> Result<Round> {
+        let id = (phase << PHASE_SHIFT) | aggregator_round;
+        let round = self
+            .round(self.feed, "getRoundData", &[Token::Uint(U256::from(id))])
+            .await?;
+        self.rounds
+            .lock()
+            .unwrap()
+            .insert(round.updated_at, round.clone());
+        Ok(round)
+    }
+
+    // The price at `timestamp` if it falls between two consecutive rounds
+    // that have already been read.
+    fn cached_at(&self, timestamp: u64) -> Option<f64> {
+        let rounds = self.rounds.lock().unwrap();
+        let (_, before) = rounds.range(..=timestamp).next_back()?;
+        let (_, after) = rounds
+            .range((Bound::Excluded(timestamp), Bound::Unbounded))
+            .next()?;
+        (after.id == before.id + 1).then_some(before.price)
+    }
+
+    // Binary searches the current phase for the last round updated at or
+    // before `timestamp`. Every round read on the way is kept, so later
+    // timestamps near this one are answered without asking the node. Earlier
+    // phases aren't searched, so older timestamps have no price.
+    async fn search(&self, timestamp: u64) -> Result<f64> {
+        let latest = self.round(self.feed, "latestRoundData", &[]).await?;
+        if timestamp >= latest.updated_at {
+            return Ok(latest.price);
+        }
+        self.rounds
+            .lock()
+            .unwrap()
+            .insert(latest.updated_at, latest.clone());
+        let phase = latest.id >> PHASE_SHIFT;
+        let mut lo = self.round_at(phase, 1).await?;
+        if timestamp < lo.updated_at {
+            return Err(Error::NoPriceHistory(timestamp));
+        }
+        let (mut lo_id, mut hi_id) = (1_u128, latest.id & u128::from(u64::MAX));
+        while hi_id - lo_id > 1 {
+            let mid_id = lo_id + (hi_id - lo_id) / 2;
+            let mid = self.round_at(phase, mid_id).await?;
+            if mid.updated_at <= timestamp {
+                lo = mid;
+                lo_id = mid_id;
+            } else {
+                hi_id = mid_id;
+            }
+        }
+        Ok(lo.price)
+    }
+}
+#[async_trait]
+impl PriceOracle for Chainlink {
+    async fn eth_usd(&self) -> Result<f64> {
+        Ok(self.round(self.feed, "latestRoundData", &[]).await?.price)
+    }
+    async fn eth_usd_at(&self, timestamp: u64) -> Result<f64> {
+        match self.cached_at(timestamp) {
+            Some(price) => Ok(price),
+            None => self.search(timestamp).await,
+        }
+    }
+    async fn token_usd(&self, token: &PaymentToken) -> Result<Option<f64>> {
+        match token.usd_feed() {
+            Some(feed) => {
+                let feed = feed
+                    .parse()
+                    .expect("invalid feed address in payment tokens");
+                Ok(Some(self.round(feed, "latestRoundData", &[]).await?.price))
+            }
+            None if token.is_eth() => Ok(Some(self.eth_usd().await?)),
+            None if token.is_stablecoin() => Ok(Some(1.0)),
+            None => Ok(None),
+        }
+    }
+}
+
+fn parse_address(address: &str) -> anyhow::Result<Address> {
+    address
+        .parse()
+        .map_err(|err| anyhow::anyhow!("Invalid address {}: {}", address, err))
+}
+
+// Rates for every known payment token. Tokens whose price can't be read are
+// left out, so their listings are skipped rather than mispriced.
+pub async fn rates(oracle: &dyn PriceOracle) -> Rates {
+    let eth_usd = match oracle.eth_usd().await {
+        Ok(p) => Some(p),
+        Err(err) => {
+            warn!(error = %err, "Couldnt get ETH/USD price");
+            None
+        }
+    };
+    let mut rates = Rates::new(eth_usd);
+    for token in PaymentToken::known() {
+        match oracle.token_usd(&token).await {
+            Ok(Some(usd)) => rates.set_usd(&token, usd),
+            Ok(None) => {}
+            Err(err) => warn!(token = %token.symbol, error = %err, "Couldnt get token price"),
+        }
+    }
+    rates
+}
+
+pub fn from_config(config: &Config) -> anyhow::Result<Arc<dyn PriceOracle>> {
+    Ok(match config.eth_usd_fixed {
+        Some(price) => Arc::new(FixedPrice(price)),
+        None => Arc::new(Chainlink::new(&config.node_url, &config.eth_usd_feed)?),
+    })
+}
+
+// Prices every sale in USD at the current rate.
+pub fn fill_usd(sales: &mut [Sale], eth_usd: f64) {
+    for sale in sales {
+        sale.price_usd = Some(sale.price_eth() * eth_usd);
+    }
+}
+
+// Prices indexed sales in USD at the rate in effect when each happened. Sales
+// in other tokens, or whose rate can't be read, are left without a USD price.
+pub async fn fill_sale_usd(oracle: &dyn PriceOracle, sales: &mut [SaleRecord]) {
+    for sale in sales.iter_mut().filter(|s| s.price_usd.is_none()) {
+        let units = sale.payment_token.to_units(sale.amount);
+        if sale.payment_token.is_stablecoin() {
+            sale.price_usd = Some(units);
+        } else if sale.payment_token.is_eth() {
+            match oracle.eth_usd_at(sale.timestamp).await {
+                Ok(eth_usd) => sale.price_usd = Some(units * eth_usd),
+                Err(err) => {
+                    warn!(tx_hash = ?sale.tx_hash, error = %err, "Couldnt get historical ETH/USD price")
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use thiserror::Error;
+use web3::types::U256;
+
+pub const NATIVE_ETH: &str = "0x0000000000000000000000000000000000000000";
+pub const WETH: &str = "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2";
+pub const ETH_DECIMALS: u8 = 18;
+// Fixed-point scale exchange rates are carried at when converting amounts.
+const RATE_DECIMALS: u8 = 18;
+
+// Tokens listings are commonly priced in: (address, symbol, decimals, Chainlink USD feed).
+// Stablecoins have no feed and are taken at their peg.
+const KNOWN_TOKENS: &[(&str, &str, u8, Option<&str>)] = &[
+    (NATIVE_ETH, "ETH", ETH_DECIMALS, None),
+    (WETH, "WETH", 18, None),
+    (
+        "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+        "USDC",
+        6,
+        None,
+    ),
+    (
+        "0xdac17f958d2ee523a2206206994597c13d831ec7",
+        "USDT",
+        6,
+        None,
+    ),
+    (
+        "0x6b175474e89094c44da98b954eedeac495271d0f",
+        "DAI",
+        18,
+        None,
+    ),
+    (
+        "0x4d224452801aced8b2f0aebe155379bb5d594381",
+        "APE",
+        18,
+        Some("0xD10aBbC76679a20055E167BB80A24ac851b37056"),
+    ),
+];
+const STABLECOINS: &[&str] = &["USDC", "USDT", "DAI"];
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PaymentToken {
+    pub address: String,
+    pub symbol: String,
+    pub decimals: u8,
+}
+impl Default for PaymentToken {
+    fn default() -> Self {
+        PaymentToken::eth()
+    }
+}
+impl PaymentToken {
+    pub fn eth() -> Self {
+        PaymentToken::from_address(NATIVE_ETH).unwrap()
+    }
+    pub fn from_address(address: &str) -> Option<Self> {
+        KNOWN_TOKENS
+            .iter()
+            .find(|(a, ..)| a.eq_ignore_ascii_case(address))
+            .map(|(a, s, d, _)| PaymentToken {
+                address: a.to_string(),
+                symbol: s.to_string(),
+                decimals: *d,
+            })
+    }
+    // Known token at `address`, or an unknown one that prices won't be found for.
+    pub fn resolve(address: &str) -> Self {
+        PaymentToken::from_address(address).unwrap_or(PaymentToken {
+            address: address.to_lowercase(),
+            symbol: String::from("UNKNOWN"),
+            decimals: 18,
+        })
+    }
+    pub fn from_symbol(symbol: &str) -> Option<Self> {
+        KNOWN_TOKENS
+            .iter()
+            .find(|(_, s, ..)| s.eq_ignore_ascii_case(symbol))
+            .and_then(|(a, ..)| PaymentToken::from_address(a))
+    }
+    // Every token the oracle should be asked about, other than ETH itself.
+    pub fn known() -> Vec<Self> {
+        KNOWN_TOKENS
+            .iter()
+            .filter_map(|(a, ..)| PaymentToken::from_address(a))
+            .filter(|t| !t.is_eth())
+            .collect()
+    }
+    // ETH and WETH trade 1:1. Decided by address, since anyone can deploy a
+    // token called WETH.
+    pub fn is_eth(&self) -> bool {
+        self.address.eq_ignore_ascii_case(NATIVE_ETH) || self.address.eq_ignore_ascii_case(WETH)
+    }
+    pub fn is_stablecoin(&self) -> bool {
+        PaymentToken::from_address(&self.address)
+            .is_some_and(|known| STABLECOINS.contains(&known.symbol.as_str()))
+    }
+    pub fn usd_feed(&self) -> Option<&'static str> {
+        KNOWN_TOKENS
+            .iter()
+            .find(|(a, ..)| a.eq_ignore_ascii_case(&self.address))
+            .and_then(|(.., feed)| *feed)
+    }
+    // `amount` in whole tokens. Lossy, so only for display and metrics.
+    pub fn to_units(&self, amount: U256) -> f64 {
+        format_units(amount, self.decimals)
+            .parse()
+            .unwrap_or(f64::NAN)
+    }
+}
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum AmountError {
+    #[error("Amount is empty")]
+    Empty,
+    #[error("Invalid character {0:?} in amount")]
+    InvalidDigit(char),
+    #[error("Amount has more than {0} decimal places")]
+    TooPrecise(u8),
+    #[error("Amount doesnt fit in 256 bits")]
+    Overflow,
+}
+
+// Exact amount in the smallest unit of a `decimals` token, e.g. "1.5" with 18
+// decimals is 1500000000000000000. Trailing zeros past `decimals` are allowed.
+pub fn parse_units(s: &str, decimals: u8) -> Result<U256, AmountError> {
+    let s = s.trim();
+    let (whole, frac) = s.split_once('.').unwrap_or((s, ""));
+    let frac = frac.trim_end_matches('0');
+    if whole.is_empty() && frac.is_empty() {
+        return Err(AmountError::Empty);
+    }
+    if frac.len() > decimals.into() {
+        return Err(AmountError::TooPrecise(decimals));
+    }
+    let padding = usize::from(decimals) - frac.len();
+    whole
+        .chars()
+        .chain(frac.chars())
+        .chain(std::iter::repeat_n('0', padding))
+        .try_fold(U256::zero(), |acc, c| {
+            let digit = c.to_digit(10).ok_or(AmountError::InvalidDigit(c))?;
+            acc.checked_mul(10.into())
+                .and_then(|acc| acc.checked_add(digit.into()))
+                .ok_or(AmountError::Overflow)
+        })
+}
+
+// Integer amount of a price string. OpenSea sometimes sends whole amounts with
+// a zero fractional part, e.g. "1500000000000000000.0".
+pub fn parse_amount(s: &str) -> Result<U256, AmountError> {
+    parse_units(s, 0)
+}
+
+// Exact decimal string of `amount` in whole tokens, without trailing zeros.
+pub fn format_units(amount: U256, decimals: u8) -> String {
+    let digits = format!(
+        "{:0>width$}",
+        amount.to_string(),
+        width = usize::from(decimals) + 1
+    );
+    let (whole, frac) = digits.split_at(digits.len() - usize::from(decimals));
+    match frac.trim_end_matches('0') {
+        "" => whole.to_string(),
+        frac => format!("{}.{}", whole, frac),
+    }
+}
+
+pub fn wei_to_eth(wei: U256) -> f64 {
+    PaymentToken::eth().to_units(wei)
+}
+
+// Rescales `amount` from `from` decimals to `to` decimals, rounding down.
+fn rescale(amount: U256, from: u8, to: u8) -> Option<U256> {
+    if to >= from {
+        amount.checked_mul(U256::exp10(usize::from(to - from)))
+    } else {
+        Some(amount / U256::exp10(usize::from(from - to)))
+    }
+}
+
+// Serializes U256 as a decimal string. Reads decimal or 0x-prefixed hex, which
+// is how caches written before amounts were decimal stored them.
+pub mod decimal {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use web3::types::U256;
+
+    pub fn serialize<S: Serializer>(value: &U256, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<U256, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        match s.strip_prefix("0x") {
+            Some(hex) => U256::from_str_radix(hex, 16).map_err(D::Error::custom),
+            None => super::parse_amount(&s).map_err(D::Error::custom),
+        }
+    }
+}
+
+// Exchange rates for one update. ETH and WETH always convert; anything else
+// needs its USD price and the ETH/USD price.
+#[derive(Debug, Clone, Default)]
+pub struct Rates {
+    eth_usd: Option<f64>,
+    usd: HashMap<String, f64>,
+}
+impl Rates {
+    pub fn new(eth_usd: Option<f64>) -> Self {
+        Rates {
+            eth_usd,
+            usd: HashMap::new(),
+        }
+    }
+    pub fn eth_usd(&self) -> Option<f64> {
+        self.eth_usd
+    }
+    pub fn set_usd(&mut self, token: &PaymentToken, usd: f64) {
+        self.usd.insert(token.address.to_lowercase(), usd);
+    }
+    // ETH-equivalent of `amount` of `token` in wei, if there's a rate for it.
+    // ETH and WETH are exact. Other tokens go through the rate, which is only
+    // as precise as the feeds behind it.
+    pub fn to_wei(&self, token: &PaymentToken, amount: U256) -> Option<U256> {
+        let wei = rescale(amount, token.decimals, ETH_DECIMALS)?;
+        if token.is_eth() {
+            return Some(wei);
+        }
+        let usd = self.usd.get(&token.address.to_lowercase())?;
+        let rate = usd / self.eth_usd?;
+        if !rate.is_finite() || rate < 0.0 {
+            return None;
+        }
+        let rate = (rate * 10_f64.powi(RATE_DECIMALS.into())).round() as u128;
+        Some(wei.checked_mul(rate.into())? / U256::exp10(RATE_DECIMALS.into()))
+    }
+}
@@ -0,0 +1,345 @@
+mod multicall;
+mod pool;
+mod tokens;
+
+pub use multicall::{Multicall, MULTICALL3};
+pub use pool::RpcPool;
+pub use tokens::{Snapshot, TokenReader, TokenState};
+
+use crate::{
+    config::Config,
+    error::Result,
+    metrics::{RPC_BATCH_DURATION, RPC_BATCH_SIZE, RPC_CALL_FAILURES},
+};
+use anyhow::anyhow;
+use serde_json::Value;
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+use web3::{
+    transports::{Batch, Http},
+    types::{Address, BlockId, BlockNumber, Bytes, CallRequest, U64},
+    Web3,
+};
+
+// How JSON-RPC batches are sent. Each batch of up to `batch_size` calls is
+// retried on its own, moving to the next endpoint when one fails.
+#[derive(Debug, Clone, Copy)]
+pub struct RpcPolicy {
+    pub batch_size: usize,
+    pub max_retries: u8,
+    pub backoff: Duration,
+    // How long a failing endpoint is skipped for.
+    pub cooldown: Duration,
+}
+impl Default for RpcPolicy {
+    fn default() -> Self {
+        RpcPolicy {
+            batch_size: 1_000,
+            max_retries: 3,
+            backoff: Duration::from_millis(500),
+            cooldown: Duration::from_secs(30),
+        }
+    }
+}
+
+// How contract reads are sent. `Batch` puts every call in one JSON-RPC batch,
+// `Multicall` packs them into Multicall3 calls of `chunk_size` each.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadMode {
+    Batch,
+    Multicall,
+}
+impl FromStr for ReadMode {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_lowercase().as_str() {
+            "batch" => Ok(ReadMode::Batch),
+            "multicall" => Ok(ReadMode::Multicall),
+            other => Err(anyhow!("Unknown read mode: {}", other)),
+        }
+    }
+}
+
+// One `eth_call`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Call {
+    pub to: Address,
+    pub data: Bytes,
+}
+impl Call {
+    pub fn new(to: Address, func: &ethabi::Function, args: &[ethabi::Token]) -> Result<Self> {
+        Ok(Call {
+            to,
+            data: func.encode_input(args)?.into(),
+        })
+    }
+    fn request(&self) -> CallRequest {
+        CallRequest::builder()
+            .to(self.to)
+            .data(self.data.clone())
+            .build()
+    }
+}
+
+// What one call returned, hex encoded, or why it failed.
+pub type CallResult = std::result::Result<Value, web3::Error>;
+
+// Reads many contract calls at once, all at the same block. Calls fail one by
+// one, so a read only errors if no endpoint can be reached.
+pub struct ContractReader {
+    pool: RpcPool,
+    policy: RpcPolicy,
+    mode: ReadMode,
+    chunk_size: usize,
+    multicall: Multicall,
+}
+impl ContractReader {
+    // `node_urls` in order of preference.
+    pub fn new(node_urls: &[String]) -> anyhow::Result<Self> {
+        let policy = RpcPolicy::default();
+        Ok(ContractReader {
+            pool: RpcPool::new(node_urls, policy.cooldown)?,
+            policy,
+            mode: ReadMode::Batch,
+            chunk_size: 500,
+            multicall: Multicall::new()?,
+        })
+    }
+
+    pub fn set_policy(&mut self, policy: RpcPolicy) {
+        self.pool.set_cooldown(policy.cooldown);
+        self.policy = RpcPolicy {
+            batch_size: policy.batch_size.max(1),
+            ..policy
+        };
+    }
+
+    pub fn set_mode(&mut self, mode: ReadMode) {
+        self.mode = mode;
+    }
+
+    pub fn set_chunk_size(&mut self, chunk_size: usize) {
+        self.chunk_size = chunk_size.max(1);
+    }
+
+    // Latest block. Reads of one sweep are pinned to it so they agree with each other.
+    pub async fn block_number(&self) -> Result<u64> {
+        let res = self
+            .send("block_number", |web3| {
+                web3.eth().block_number();
+            })
+            .await?;
+        quantity(&first(res)?)
+    }
+
+    // When block `number` was mined, or None if the chain isn't that long yet.
+    pub async fn block_timestamp(&self, number: u64) -> Result<Option<u64>> {
+        let res = self
+            .send("block", |web3| {
+                web3.eth()
+                    .block(BlockId::Number(BlockNumber::Number(number.into())));
+            })
+            .await?;
+        match first(res)? {
+            Value::Null => Ok(None),
+            block => Ok(Some(quantity(&block["timestamp"])?)),
+        }
+    }
+
+    // Last block mined at or before `timestamp`, found by binary search over
+    // block timestamps. None if `timestamp` is before the first block.
+    pub async fn block_at(&self, timestamp: u64) -> Result<Option<u64>> {
+        let head = self.block_number().await?;
+        if self.mined_at(0).await? > timestamp {
+            return Ok(None);
+        }
+        if self.mined_at(head).await? <= timestamp {
+            return Ok(Some(head));
+        }
+        // `low` is always mined at or before `timestamp` and `high` after it.
+        let (mut low, mut high) = (0, head);
+        while high - low > 1 {
+            let mid = low + (high - low) / 2;
+            if self.mined_at(mid).await? <= timestamp {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        Ok(Some(low))
+    }
+
+    async fn mined_at(&self, number: u64) -> Result<u64> {
+        match self.block_timestamp(number).await? {
+            Some(timestamp) => Ok(timestamp),
+            None => Err(web3::Error::InvalidResponse(format!("Block {} not found", number)).into()),
+        }
+    }
+
+    // Results line up with `calls`. `label` names the read in metrics.
+    pub async fn read(&self, label: &str, calls: &[Call], block: u64) -> Result<Vec<CallResult>> {
+        let results = match self.mode {
+            ReadMode::Multicall if self.multicall_deployed(block).await? => {
+                self.read_multicall(label, calls, block).await?
+            }
+            ReadMode::Multicall => {
+                warn!(
+                    block,
+                    "Multicall3 isnt deployed at this block. Reading with a batch"
+                );
+                self.read_batch(label, calls, block).await?
+            }
+            ReadMode::Batch => self.read_batch(label, calls, block).await?,
+        };
+        let failed = results.iter().filter(|r| r.is_err()).count();
+        RPC_CALL_FAILURES
+            .with_label_values(&[label])
+            .inc_by(failed as u64);
+        Ok(results)
+    }
+
+    async fn read_multicall(
+        &self,
+        label: &str,
+        calls: &[Call],
+        block: u64,
+    ) -> Result<Vec<CallResult>> {
+        let chunks: Vec<&[Call]> = calls.chunks(self.chunk_size).collect();
+        let requests = chunks
+            .iter()
+            .map(|chunk| self.multicall.encode(chunk))
+            .collect::<Result<Vec<CallRequest>>>()?;
+        let answers = self.submit(label, requests, block).await?;
+        let mut results = Vec::with_capacity(calls.len());
+        for (chunk, answer) in chunks.into_iter().zip(answers) {
+            let decoded = answer
+                .map_err(Into::into)
+                .and_then(|raw| self.multicall.decode(&raw, chunk.len()));
+            match decoded {
+                Ok(decoded) => results.extend(decoded),
+                Err(err) => {
+                    warn!(calls = chunk.len(), error = %err, "Multicall failed. Retrying chunk with a batch");
+                    results.extend(self.read_batch(label, chunk, block).await?);
+                }
+            }
+        }
+        info!(calls = calls.len(), "Read with multicall");
+        Ok(results)
+    }
+
+    async fn read_batch(&self, label: &str, calls: &[Call], block: u64) -> Result<Vec<CallResult>> {
+        self.submit(label, calls.iter().map(Call::request).collect(), block)
+            .await
+    }
+
+    // Sends `requests` in batches of `batch_size`, one after the other.
+    async fn submit(
+        &self,
+        label: &str,
+        requests: Vec<CallRequest>,
+        block: u64,
+    ) -> Result<Vec<CallResult>> {
+        let block = BlockId::Number(BlockNumber::Number(block.into()));
+        let mut results = Vec::with_capacity(requests.len());
+        for chunk in requests.chunks(self.policy.batch_size) {
+            RPC_BATCH_SIZE
+                .with_label_values(&[label])
+                .observe(chunk.len() as f64);
+            let res = self
+                .send(label, |web3| {
+                    for req in chunk {
+                        web3.eth().call(req.clone(), Some(block));
+                    }
+                })
+                .await?;
+            results.extend(res);
+        }
+        Ok(results)
+    }
+
+    // Sends the calls `queue` adds as one batch. If the batch can't be
+    // delivered the endpoint is benched and the batch goes to the next one.
+    async fn send<F>(&self, label: &str, queue: F) -> Result<Vec<CallResult>>
+    where
+        F: Fn(&Web3<Batch<Http>>),
+    {
+        let mut attempt = 0;
+        loop {
+            let index = self.pool.pick();
+            let web3 = self.pool.web3(index);
+            let start = Instant::now();
+            // The reader owns the transport and a batch is drained even when it
+            // fails, so nothing queued earlier can shift the results.
+            queue(web3);
+            match web3.transport().submit_batch().await {
+                Ok(res) => {
+                    self.pool.record_success(index);
+                    RPC_BATCH_DURATION
+                        .with_label_values(&[label])
+                        .observe(start.elapsed().as_secs_f64());
+                    return Ok(res);
+                }
+                Err(err) => {
+                    self.pool.record_failure(index);
+                    if attempt >= self.policy.max_retries {
+                        return Err(err.into());
+                    }
+                    attempt += 1;
+                    warn!(endpoint = self.pool.label(index), attempt, error = %err, "RPC batch failed. Retrying");
+                    tokio::time::sleep(self.policy.backoff).await;
+                }
+            }
+        }
+    }
+
+    async fn multicall_deployed(&self, block: u64) -> Result<bool> {
+        let address = self.multicall.address();
+        let res = self
+            .send("code", |web3| {
+                web3.eth()
+                    .code(address, Some(BlockNumber::Number(block.into())));
+            })
+            .await?;
+        match res.into_iter().next() {
+            Some(code) => Ok(!matches!(code?.as_str(), Some("0x") | Some(""))),
+            None => Ok(false),
+        }
+    }
+}
+
+fn first(res: Vec<CallResult>) -> Result<Value> {
+    match res.into_iter().next() {
+        Some(raw) => Ok(raw?),
+        None => Err(web3::Error::InvalidResponse(String::from("Empty batch")).into()),
+    }
+}
+
+// A reader over the node and its fallbacks, with the configured batching.
+pub fn from_config(config: &Config) -> anyhow::Result<ContractReader> {
+    let mut reader = ContractReader::new(
+        &[
+            vec![config.node_url.clone()],
+            config.rpc_fallback_urls.clone(),
+        ]
+        .concat(),
+    )?;
+    reader.set_policy(RpcPolicy {
+        batch_size: config.rpc_batch_size,
+        max_retries: config.rpc_max_retries,
+        backoff: config.rpc_backoff,
+        cooldown: config.rpc_cooldown,
+    });
+    reader.set_mode(config.read_mode);
+    reader.set_chunk_size(config.multicall_chunk_size);
+    Ok(reader)
+}
+
+// A hex encoded JSON-RPC quantity.
+fn quantity(raw: &Value) -> Result<u64> {
+    let number: U64 = serde_json::from_value(raw.clone())
+        .map_err(|err| web3::Error::InvalidResponse(err.to_string()))?;
+    Ok(number.as_u64())
+}
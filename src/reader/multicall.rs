@@ -0,0 +1,77 @@
+use crate::{
+    error::Result,
+    reader::{Call, CallResult},
+};
+use ethabi::Token;
+use serde_json::Value;
+use std::fs::File;
+use web3::types::{Address, CallRequest};
+
+// Multicall3 is deployed at the same address on mainnet and most other chains.
+pub const MULTICALL3: &str = "0xcA11bde05977b3631167028862bE2a173976CA11";
+
+// Packs many calls into one `aggregate3` call. Each call is allowed to fail on
+// its own, so one revert doesn't take down the rest of the chunk.
+pub struct Multicall {
+    address: Address,
+    aggregate: ethabi::Function,
+}
+impl Multicall {
+    pub fn new() -> anyhow::Result<Self> {
+        let abi = ethabi::Contract::load(File::open("src/utils/multicall3_abi.json")?)?;
+        Ok(Multicall {
+            address: MULTICALL3.parse()?,
+            aggregate: abi.function("aggregate3")?.clone(),
+        })
+    }
+
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    pub fn encode(&self, calls: &[Call]) -> Result<CallRequest> {
+        let calls = calls
+            .iter()
+            .map(|call| {
+                Token::Tuple(vec![
+                    Token::Address(call.to),
+                    Token::Bool(true),
+                    Token::Bytes(call.data.0.clone()),
+                ])
+            })
+            .collect();
+        let data = self.aggregate.encode_input(&[Token::Array(calls)])?;
+        Ok(CallRequest::builder()
+            .to(self.address)
+            .data(data.into())
+            .build())
+    }
+
+    // Splits the answer to `aggregate3` back into one result per call, in the
+    // shape a JSON-RPC batch would have returned them.
+    pub fn decode(&self, raw: &Value, expected: usize) -> Result<Vec<CallResult>> {
+        let bytes = raw
+            .as_str()
+            .and_then(|s| hex::decode(s.trim_start_matches("0x")).ok())
+            .ok_or(ethabi::Error::InvalidData)?;
+        let results = match self.aggregate.decode_output(&bytes)?.pop() {
+            Some(Token::Array(results)) if results.len() == expected => results,
+            _ => return Err(ethabi::Error::InvalidData.into()),
+        };
+        results
+            .into_iter()
+            .map(|result| match result {
+                Token::Tuple(fields) => match fields.as_slice() {
+                    [Token::Bool(true), Token::Bytes(data)] => {
+                        Ok(Ok(Value::String(format!("0x{}", hex::encode(data)))))
+                    }
+                    [Token::Bool(false), _] => Ok(Err(web3::Error::InvalidResponse(String::from(
+                        "Call reverted",
+                    )))),
+                    _ => Err(ethabi::Error::InvalidData.into()),
+                },
+                _ => Err(ethabi::Error::InvalidData.into()),
+            })
+            .collect()
+    }
+}
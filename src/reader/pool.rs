@@ -0,0 +1,121 @@
+use crate::{
+    metrics::{RPC_ENDPOINT_FAILURES, RPC_ENDPOINT_HEALTHY},
+    utils::get_web3,
+};
+use reqwest::Url;
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::warn;
+use web3::{
+    transports::{Batch, Http},
+    Web3,
+};
+
+struct EndpointState {
+    // Earliest time the endpoint is tried again after failing.
+    benched_until: Option<Instant>,
+    failures: u32,
+}
+
+// RPC endpoints in order of preference. A request goes to the first one that
+// isn't benched, so traffic moves to a fallback while the primary is failing
+// and comes back once its cooldown is over.
+pub struct RpcPool {
+    endpoints: Vec<(String, Web3<Batch<Http>>)>,
+    state: Mutex<Vec<EndpointState>>,
+    cooldown: Duration,
+}
+impl RpcPool {
+    pub fn new(urls: &[String], cooldown: Duration) -> anyhow::Result<Self> {
+        if urls.is_empty() {
+            return Err(anyhow::anyhow!("Need at least one RPC url"));
+        }
+        let endpoints = urls
+            .iter()
+            .map(|url| Ok((endpoint_label(url), get_web3(url)?)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        for (label, _) in &endpoints {
+            RPC_ENDPOINT_HEALTHY.with_label_values(&[label]).set(1);
+        }
+        Ok(RpcPool {
+            state: Mutex::new(
+                urls.iter()
+                    .map(|_| EndpointState {
+                        benched_until: None,
+                        failures: 0,
+                    })
+                    .collect(),
+            ),
+            endpoints,
+            cooldown,
+        })
+    }
+
+    // The preferred endpoint that isn't benched, or the one back soonest if all are.
+    pub fn pick(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        let now = Instant::now();
+        match state
+            .iter()
+            .position(|s| !matches!(s.benched_until, Some(until) if until > now))
+        {
+            Some(index) => index,
+            None => (0..state.len())
+                .min_by_key(|i| state[*i].benched_until)
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn set_cooldown(&mut self, cooldown: Duration) {
+        self.cooldown = cooldown;
+    }
+
+    pub fn web3(&self, index: usize) -> &Web3<Batch<Http>> {
+        &self.endpoints[index].1
+    }
+
+    pub fn label(&self, index: usize) -> &str {
+        &self.endpoints[index].0
+    }
+
+    pub fn record_success(&self, index: usize) {
+        let mut state = self.state.lock().unwrap();
+        if state[index].failures > 0 {
+            RPC_ENDPOINT_HEALTHY
+                .with_label_values(&[self.label(index)])
+                .set(1);
+        }
+        state[index].failures = 0;
+        state[index].benched_until = None;
+    }
+
+    // Benches the endpoint for the cooldown so the next attempt goes elsewhere.
+    pub fn record_failure(&self, index: usize) {
+        let mut state = self.state.lock().unwrap();
+        state[index].failures += 1;
+        state[index].benched_until = Some(Instant::now() + self.cooldown);
+        let label = self.label(index);
+        warn!(
+            endpoint = label,
+            failures = state[index].failures,
+            cooldown_secs = self.cooldown.as_secs_f64(),
+            "Benching RPC endpoint"
+        );
+        RPC_ENDPOINT_FAILURES.with_label_values(&[label]).inc();
+        RPC_ENDPOINT_HEALTHY.with_label_values(&[label]).set(0);
+    }
+}
+
+// Host and port only. Provider urls usually carry the API key in the path.
+fn endpoint_label(url: &str) -> String {
+    match Url::parse(url) {
+        Ok(parsed) => match (parsed.host_str(), parsed.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            _ => String::from("unknown"),
+        },
+        Err(_) => String::from("unknown"),
+    }
+}
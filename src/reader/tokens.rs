@@ -0,0 +1,131 @@
+use crate::{
+    error::{Error, Result},
+    reader::{Call, CallResult, ContractReader},
+    utils::{parse_bio, parse_name, parse_owner},
+};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use tracing::info;
+use web3::types::Address;
+
+const NAMING_CONTRACT: [u8; 20] = hex_literal::hex!("02afD7FD5B1C190506F538B36e7741a2F33D715d");
+const KONG_CONTRACT: [u8; 20] = hex_literal::hex!("Ef0182dc0574cd5874494a120750FD222FdB909a");
+
+// What a Kong was called and who held it at one block.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct TokenState {
+    pub token_id: i16,
+    pub name: String,
+    pub bio: Option<String>,
+    pub owner: Option<String>,
+}
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub block: u64,
+    pub timestamp: u64,
+    pub tokens: Vec<TokenState>,
+}
+
+// Per-token getters of the Kong contracts. Names and bios live on the naming
+// contract, owners on the token contract.
+pub struct TokenReader {
+    reader: ContractReader,
+    naming: ethabi::Contract,
+    erc721: ethabi::Contract,
+}
+impl TokenReader {
+    pub fn new(reader: ContractReader) -> anyhow::Result<Self> {
+        Ok(TokenReader {
+            reader,
+            naming: ethabi::Contract::load(File::open("src/utils/kong_naming_abi.json")?)?,
+            erc721: ethabi::Contract::load(File::open("src/utils/erc721_abi.json")?)?,
+        })
+    }
+
+    pub fn reader(&self) -> &ContractReader {
+        &self.reader
+    }
+
+    pub async fn names(&self, ids: &[i16], block: u64) -> Result<Vec<String>> {
+        let res = self.read("names", ids, block).await?;
+        Ok(ids
+            .iter()
+            .zip(res.iter())
+            .map(|(id, raw)| parse_name(raw, id))
+            .collect())
+    }
+
+    pub async fn bios(&self, ids: &[i16], block: u64) -> Result<Vec<Option<String>>> {
+        let res = self.read("bios", ids, block).await?;
+        Ok(res.iter().map(parse_bio).collect())
+    }
+
+    // Tokens that don't exist at `block` have no owner.
+    pub async fn owners(&self, ids: &[i16], block: u64) -> Result<Vec<Option<String>>> {
+        let res = self.read("ownerOf", ids, block).await?;
+        Ok(res.iter().map(parse_owner).collect())
+    }
+
+    // Where each token's metadata lives. Tokens whose URI can't be read are None.
+    pub async fn token_uris(&self, ids: &[i16], block: u64) -> Result<Vec<Option<String>>> {
+        let func = self.erc721.function("tokenURI")?;
+        let res = self.read("tokenURI", ids, block).await?;
+        Ok(res
+            .iter()
+            .map(|raw| {
+                let bytes =
+                    hex::decode(raw.as_ref().ok()?.as_str()?.trim_start_matches("0x")).ok()?;
+                func.decode_output(&bytes).ok()?.pop()?.into_string()
+            })
+            .collect())
+    }
+
+    // Names, bios and owners of `ids` as of `block`.
+    pub async fn snapshot(&self, ids: &[i16], block: u64) -> Result<Snapshot> {
+        let timestamp = self
+            .reader
+            .block_timestamp(block)
+            .await?
+            .ok_or(Error::UnknownBlock(block))?;
+        let names = self.names(ids, block).await?;
+        let bios = self.bios(ids, block).await?;
+        let owners = self.owners(ids, block).await?;
+        let tokens = ids
+            .iter()
+            .zip(names)
+            .zip(bios)
+            .zip(owners)
+            .map(|(((id, name), bio), owner)| TokenState {
+                token_id: *id,
+                name,
+                bio,
+                owner,
+            })
+            .collect();
+        info!(block, timestamp, tokens = ids.len(), "Took snapshot");
+        Ok(Snapshot {
+            block,
+            timestamp,
+            tokens,
+        })
+    }
+
+    async fn read(&self, method: &str, ids: &[i16], block: u64) -> Result<Vec<CallResult>> {
+        let (abi, to) = match method {
+            "names" | "bios" => (&self.naming, NAMING_CONTRACT),
+            _ => (&self.erc721, KONG_CONTRACT),
+        };
+        let func = abi.function(method)?;
+        let calls = ids
+            .iter()
+            .map(|id| {
+                Call::new(
+                    Address::from(to),
+                    func,
+                    &[ethabi::Token::Uint((*id).into())],
+                )
+            })
+            .collect::<Result<Vec<Call>>>()?;
+        self.reader.read(method, &calls, block).await
+    }
+}
@@ -0,0 +1,60 @@
+use crate::{health::Health, metrics};
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use serde::Serialize;
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+use tracing::info;
+
+pub async fn serve(addr: SocketAddr, health: Arc<Health>) -> anyhow::Result<()> {
+    metrics::init();
+    let make_svc = make_service_fn(move |_| {
+        let health = health.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let health = health.clone();
+                async move { Ok::<_, Infallible>(route(req, &health).await) }
+            }))
+        }
+    });
+    info!(%addr, "Serving metrics and health checks");
+    Server::try_bind(&addr)?.serve(make_svc).await?;
+    Ok(())
+}
+
+async fn route(req: Request<Body>, health: &Health) -> Response<Body> {
+    match (req.method(), req.uri().path()) {
+        (&Method::GET, "/metrics") => match metrics::gather() {
+            Ok(body) => Response::new(Body::from(body)),
+            Err(err) => respond(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
+        },
+        (&Method::GET, "/healthz") => {
+            let report = health.liveness();
+            report_response(report.is_fresh(), &report)
+        }
+        (&Method::GET, "/readyz") => {
+            let report = health.report().await;
+            report_response(report.is_ready(), &report)
+        }
+        _ => respond(StatusCode::NOT_FOUND, String::from("Not found")),
+    }
+}
+
+fn report_response<R: Serialize>(ok: bool, report: &R) -> Response<Body> {
+    let status = if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    match serde_json::to_string_pretty(report) {
+        Ok(body) => respond(status, body),
+        Err(err) => respond(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
+    }
+}
+
+fn respond(status: StatusCode, body: String) -> Response<Body> {
+    let mut res = Response::new(Body::from(body));
+    *res.status_mut() = status;
+    res
+}
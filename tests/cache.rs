@@ -0,0 +1,173 @@
+use kong_scraper::{
+    cache::{CacheFormat, CacheStore},
+    kong_data::Cached,
+};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{env, fs, path::Path, time::Duration};
+use web3::types::U256;
+
+// A version 2 cache, from when sales were priced in floating point ETH.
+fn v2_cache(path: &str) {
+    let mut data = serde_json::to_value(Cached::new().unwrap()).unwrap();
+    data["data"]["3"]["current_sales"] = json!([{
+        "order_hash": null,
+        "created_timestamp": 1659355200,
+        "expiration_timestamp": 4102444800_u64,
+        "sale_type": "BuyNow",
+        "price_eth": 1.5,
+        "price_usd": 2400.0,
+        "platform": "OpenSea"
+    }, {
+        "order_hash": null,
+        "created_timestamp": 1659355500,
+        "expiration_timestamp": null,
+        "sale_type": "BuyNow",
+        "price_eth": 0.1,
+        "price_usd": null,
+        "platform": "OpenSea"
+    }]);
+    let file: Value = json!({
+        "version": 2,
+        "checksum": hex::encode(Sha256::digest(serde_json::to_vec(&data).unwrap())),
+        "data": data,
+    });
+    fs::write(path, serde_json::to_vec(&file).unwrap()).unwrap();
+}
+
+#[test]
+fn migrates_float_prices_to_wei() {
+    let path = env::temp_dir()
+        .join("kong-scraper-test-v2.cache")
+        .to_string_lossy()
+        .to_string();
+    v2_cache(&path);
+
+    let cached = CacheStore::new(path.clone(), 0, false, CacheFormat::Json)
+        .restore()
+        .unwrap()
+        .unwrap();
+    let _ = fs::remove_file(&path);
+
+    let sales = &cached.data()[&3].current_sales;
+    assert_eq!(
+        sales[0].price_wei,
+        U256::from_dec_str("1500000000000000000").unwrap()
+    );
+    assert_eq!(sales[0].amount, sales[0].price_wei);
+    assert!(sales[0].payment_token.is_eth());
+    assert_eq!(
+        sales[1].price_wei,
+        U256::from_dec_str("100000000000000000").unwrap()
+    );
+}
+
+fn temp_path(name: &str) -> String {
+    let path = env::temp_dir()
+        .join(format!(
+            "kong-scraper-test-{}-{}.cache",
+            name,
+            std::process::id()
+        ))
+        .to_string_lossy()
+        .to_string();
+    clean(&path);
+    path
+}
+
+fn clean(path: &str) {
+    for p in [
+        path.to_string(),
+        format!("{}.1", path),
+        format!("{}.2", path),
+    ] {
+        let _ = fs::remove_file(p);
+    }
+}
+
+fn named(name: &str) -> Cached {
+    let mut cached = Cached::new().unwrap();
+    cached.data_mut().get_mut(&0).unwrap().name = name.to_string();
+    cached
+}
+
+fn name_of(cached: &Cached) -> &str {
+    &cached.data()[&0].name
+}
+
+#[test]
+fn rejects_cache_with_bad_checksum() {
+    let path = temp_path("checksum");
+    let store = CacheStore::new(path.clone(), 0, false, CacheFormat::Json);
+    store.save(&named("Kong")).unwrap();
+    let mut file: Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+    file["data"]["data"]["0"]["name"] = json!("Tampered");
+    fs::write(&path, serde_json::to_vec(&file).unwrap()).unwrap();
+
+    let err = store.restore().unwrap_err().to_string();
+    clean(&path);
+    assert!(err.contains("is corrupt"), "{}", err);
+    assert!(err.contains("Checksum mismatch"), "{}", err);
+}
+
+#[test]
+fn corrupt_cache_names_backups_without_fallback() {
+    let path = temp_path("corrupt");
+    let store = CacheStore::new(path.clone(), 2, false, CacheFormat::Json);
+    store.save(&named("First")).unwrap();
+    store.save(&named("Second")).unwrap();
+    fs::write(&path, b"{ not json").unwrap();
+
+    let err = store.restore().unwrap_err().to_string();
+    clean(&path);
+    assert!(err.contains(&format!("{}.1", path)), "{}", err);
+}
+
+#[test]
+fn falls_back_to_newest_usable_backup() {
+    let path = temp_path("fallback");
+    let store = CacheStore::new(path.clone(), 2, true, CacheFormat::Binary);
+    store.save(&named("First")).unwrap();
+    store.save(&named("Second")).unwrap();
+    store.save(&named("Third")).unwrap();
+    // The newest backup is as broken as the cache, the one before it is fine.
+    fs::write(&path, b"garbage").unwrap();
+    fs::write(format!("{}.1", path), b"garbage").unwrap();
+
+    let cached = store.restore().unwrap().unwrap();
+    clean(&path);
+    assert_eq!(name_of(&cached), "First");
+}
+
+#[test]
+fn fails_when_no_backup_is_usable() {
+    let path = temp_path("nobackup");
+    let store = CacheStore::new(path.clone(), 1, true, CacheFormat::Json);
+    store.save(&named("First")).unwrap();
+    store.save(&named("Second")).unwrap();
+    fs::write(&path, b"garbage").unwrap();
+    fs::write(format!("{}.1", path), b"garbage").unwrap();
+
+    let err = store.restore().unwrap_err().to_string();
+    clean(&path);
+    assert!(err.contains("no usable backup"), "{}", err);
+}
+
+#[test]
+fn rotates_backups_once_per_interval() {
+    let path = temp_path("interval");
+    let mut store = CacheStore::new(path.clone(), 2, true, CacheFormat::Json);
+    store.set_backup_interval(Duration::from_secs(3600));
+    for name in ["First", "Second", "Third", "Fourth"] {
+        store.save(&named(name)).unwrap();
+    }
+    let backup = fs::read(format!("{}.1", path)).unwrap();
+    let second_exists = Path::new(&format!("{}.2", path)).exists();
+    clean(&path);
+
+    // Only the first overwrite took a backup, so a bad cache written by the
+    // later saves can't reach it.
+    assert!(!second_exists);
+    let file: Value = serde_json::from_slice(&backup).unwrap();
+    assert_eq!(file["data"]["data"]["0"]["name"], "First");
+}
@@ -0,0 +1,347 @@
+#![allow(dead_code)]
+
+use hyper::{
+    body,
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server, StatusCode,
+};
+use kong_scraper::{config::Config, kong_data::ScaperBot};
+use reqwest::Url;
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::Infallible,
+    env, fs,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+pub const CONTRACT: &str = "0xEf0182dc0574cd5874494a120750FD222FdB909a";
+
+pub fn fixture(name: &str) -> String {
+    fs::read_to_string(format!("tests/fixtures/{}", name))
+        .unwrap_or_else(|_| panic!("missing fixture {}", name))
+}
+
+#[derive(Clone, Debug)]
+pub struct Reply {
+    pub status: u16,
+    pub body: String,
+    pub delay: Duration,
+}
+impl Reply {
+    pub fn ok(fixture_name: &str) -> Self {
+        Reply {
+            status: 200,
+            body: fixture(fixture_name),
+            delay: Duration::ZERO,
+        }
+    }
+    pub fn status(status: u16) -> Self {
+        Reply {
+            status,
+            body: String::new(),
+            delay: Duration::ZERO,
+        }
+    }
+    pub fn delayed(self, delay: Duration) -> Self {
+        Reply { delay, ..self }
+    }
+}
+
+// Replies are served in order and the last one repeats once the rest are used up.
+struct Route {
+    path: String,
+    query: Vec<(String, String)>,
+    replies: VecDeque<Reply>,
+    hits: usize,
+}
+
+#[derive(Clone, Default)]
+pub struct MockOpensea {
+    routes: Arc<Mutex<Vec<Route>>>,
+    rejected_keys: Arc<Mutex<HashMap<String, u16>>>,
+    key_hits: Arc<Mutex<HashMap<String, usize>>>,
+}
+impl MockOpensea {
+    pub fn new() -> Self {
+        MockOpensea::default()
+    }
+
+    // The first route whose path matches and whose query pairs are all present wins.
+    pub fn route(self, path: &str, query: &[(&str, &str)], replies: Vec<Reply>) -> Self {
+        self.routes.lock().unwrap().push(Route {
+            path: path.to_string(),
+            query: query
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            replies: replies.into(),
+            hits: 0,
+        });
+        self
+    }
+
+    pub fn events(self, event_type: &str, cursor: Option<&str>, replies: Vec<Reply>) -> Self {
+        let mut query = vec![("event_type", event_type)];
+        if let Some(c) = cursor {
+            query.push(("cursor", c));
+        }
+        self.route("/api/v1/events", &query, replies)
+    }
+
+    pub fn listings(self, token_id: i16, replies: Vec<Reply>) -> Self {
+        let path = format!("/api/v1/asset/{}/{}/listings", CONTRACT, token_id);
+        self.route(&path, &[], replies)
+    }
+
+    // Every request made with `key` is answered with `status`, whatever the route.
+    pub fn reject_key(self, key: &str, status: u16) -> Self {
+        self.rejected_keys
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), status);
+        self
+    }
+
+    pub fn accept_key(&self, key: &str) {
+        self.rejected_keys.lock().unwrap().remove(key);
+    }
+
+    pub fn key_hits(&self, key: &str) -> usize {
+        *self.key_hits.lock().unwrap().get(key).unwrap_or(&0)
+    }
+
+    pub fn hits(&self, path: &str) -> usize {
+        self.routes
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|r| r.path == path)
+            .map(|r| r.hits)
+            .sum()
+    }
+
+    pub fn listing_hits(&self, token_id: i16) -> usize {
+        self.hits(&format!("/api/v1/asset/{}/{}/listings", CONTRACT, token_id))
+    }
+
+    // Binds to an ephemeral port and returns the base url to point the client at.
+    pub async fn start(&self) -> String {
+        let mock = self.clone();
+        let make_svc = make_service_fn(move |_| {
+            let mock = mock.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let mock = mock.clone();
+                    async move {
+                        let reply = mock.reply(req);
+                        tokio::time::sleep(reply.delay).await;
+                        let mut res = Response::new(Body::from(reply.body));
+                        *res.status_mut() = StatusCode::from_u16(reply.status).unwrap();
+                        Ok::<_, Infallible>(res)
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_svc);
+        let url = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+        url
+    }
+
+    fn reply(&self, req: Request<Body>) -> Reply {
+        let key = req
+            .headers()
+            .get("X-API-KEY")
+            .and_then(|k| k.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        *self
+            .key_hits
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_default() += 1;
+        if let Some(status) = self.rejected_keys.lock().unwrap().get(&key) {
+            return Reply::status(*status);
+        }
+        let url = Url::parse(&format!("http://mock{}", req.uri())).unwrap();
+        let query: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+        let mut routes = self.routes.lock().unwrap();
+        let route = routes
+            .iter_mut()
+            .filter(|r| r.path == url.path())
+            .find(|r| r.query.iter().all(|pair| query.contains(pair)));
+        match route {
+            Some(r) => {
+                r.hits += 1;
+                if r.replies.len() > 1 {
+                    r.replies.pop_front().unwrap()
+                } else {
+                    r.replies.front().cloned().unwrap()
+                }
+            }
+            None => Reply::status(404),
+        }
+    }
+}
+
+// Answers one JSON-RPC call from its method and params, or fails it with a message.
+pub type RpcHandler = dyn Fn(&str, &Value) -> Result<Value, String> + Send + Sync;
+
+// A JSON-RPC node answering single and batched calls with `handler`.
+pub async fn start_node(handler: Arc<RpcHandler>) -> String {
+    start_node_recording(handler, Arc::new(Mutex::new(Vec::new()))).await
+}
+
+// Like `start_node`, also pushing the number of calls in every request to `batches`.
+pub async fn start_node_recording(
+    handler: Arc<RpcHandler>,
+    batches: Arc<Mutex<Vec<usize>>>,
+) -> String {
+    let make_svc = make_service_fn(move |_| {
+        let handler = handler.clone();
+        let batches = batches.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let handler = handler.clone();
+                let batches = batches.clone();
+                async move {
+                    let bytes = body::to_bytes(req.into_body()).await.unwrap();
+                    let call: Value = serde_json::from_slice(&bytes).unwrap();
+                    batches
+                        .lock()
+                        .unwrap()
+                        .push(call.as_array().map_or(1, Vec::len));
+                    let answer = |call: &Value| {
+                        let method = call["method"].as_str().unwrap_or_default();
+                        match handler(method, &call["params"]) {
+                            Ok(result) => {
+                                json!({"jsonrpc": "2.0", "id": call["id"], "result": result})
+                            }
+                            Err(message) => json!({
+                                "jsonrpc": "2.0",
+                                "id": call["id"],
+                                "error": {"code": -32000, "message": message},
+                            }),
+                        }
+                    };
+                    let res = match call.as_array() {
+                        Some(batch) => Value::Array(batch.iter().map(answer).collect()),
+                        None => answer(&call),
+                    };
+                    Ok::<_, Infallible>(Response::new(Body::from(res.to_string())))
+                }
+            }))
+        }
+    });
+    let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_svc);
+    let url = format!("http://{}", server.local_addr());
+    tokio::spawn(server);
+    url
+}
+
+// A node that answers every request with 503. Returns its url and request count.
+pub async fn start_down_node() -> (String, Arc<Mutex<usize>>) {
+    let hits = Arc::new(Mutex::new(0));
+    let counter = hits.clone();
+    let make_svc = make_service_fn(move |_| {
+        let counter = counter.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |_req: Request<Body>| {
+                *counter.lock().unwrap() += 1;
+                let mut res = Response::new(Body::empty());
+                *res.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+                async move { Ok::<_, Infallible>(res) }
+            }))
+        }
+    });
+    let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_svc);
+    let url = format!("http://{}", server.local_addr());
+    tokio::spawn(server);
+    (url, hits)
+}
+
+// An `eth_getBlockByNumber` answer with everything but number, hash and time zeroed.
+pub fn block(number: u64, hash: &str, timestamp: u64) -> Value {
+    let zero = format!("0x{}", "00".repeat(32));
+    json!({
+        "hash": hash,
+        "parentHash": zero,
+        "sha3Uncles": zero,
+        "miner": format!("0x{}", "00".repeat(20)),
+        "stateRoot": zero,
+        "transactionsRoot": zero,
+        "receiptsRoot": zero,
+        "number": format!("0x{:x}", number),
+        "gasUsed": "0x0",
+        "gasLimit": "0x0",
+        "extraData": "0x",
+        "logsBloom": format!("0x{}", "00".repeat(256)),
+        "timestamp": format!("0x{:x}", timestamp),
+        "difficulty": "0x0",
+        "uncles": [],
+        "transactions": [],
+        "size": "0x0",
+        "mixHash": zero,
+        "nonce": "0x0000000000000000",
+    })
+}
+
+// An HTTP gateway serving the files under `root`, by request path.
+pub async fn start_gateway(root: &str) -> String {
+    let root = root.to_string();
+    let make_svc = make_service_fn(move |_| {
+        let root = root.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let res = match fs::read(format!("{}{}", root, req.uri().path())) {
+                    Ok(body) => Response::new(Body::from(body)),
+                    Err(_) => {
+                        let mut res = Response::new(Body::empty());
+                        *res.status_mut() = StatusCode::NOT_FOUND;
+                        res
+                    }
+                };
+                async move { Ok::<_, Infallible>(res) }
+            }))
+        }
+    });
+    let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_svc);
+    let url = format!("http://{}", server.local_addr());
+    tokio::spawn(server);
+    url
+}
+
+pub fn test_config(name: &str, opensea_url: &str) -> Config {
+    let dir = env::temp_dir().join(format!("kong-scraper-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let mut config = Config::new(
+        String::from("http://127.0.0.1:1"),
+        String::from("test-key"),
+        String::from("mongodb://127.0.0.1:1"),
+    );
+    config.cache_path = dir.join("cache.json").to_string_lossy().to_string();
+    config.opensea_url = opensea_url.to_string();
+    config.opensea_throttle = Duration::ZERO;
+    config.opensea_backoff = Duration::from_millis(10);
+    config.opensea_max_retries = 2;
+    config
+}
+
+pub async fn scraper(name: &str, mock: &MockOpensea) -> (ScaperBot, Config) {
+    scraper_with(name, mock, |_| {}).await
+}
+
+pub async fn scraper_with(
+    name: &str,
+    mock: &MockOpensea,
+    configure: impl FnOnce(&mut Config),
+) -> (ScaperBot, Config) {
+    let url = mock.start().await;
+    let mut config = test_config(name, &url);
+    configure(&mut config);
+    let _ = fs::remove_file(&config.cache_path);
+    (ScaperBot::init(&config).await.unwrap(), config)
+}
@@ -0,0 +1,314 @@
+mod common;
+
+use common::{block, fixture, start_node, CONTRACT};
+use kong_scraper::{
+    error::Error,
+    indexer::{CursorOptions, Poll, SaleIndexer, SaleLedger},
+    kong_data::Marketplace,
+};
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use web3::types::{Address, U256};
+
+// Blocks are twelve seconds apart from this timestamp.
+const GENESIS_TS: u64 = 1_677_600_000;
+
+// A chain serving the fixture logs. Blocks from `fork_from` on have been
+// replaced, so they carry different hashes and lose the logs of `orphaned`.
+#[derive(Default)]
+struct Chain {
+    head: u64,
+    fork_from: Option<u64>,
+    orphaned: Vec<u64>,
+    filters: Vec<Value>,
+    // Every block asked for, in order.
+    fetched: Vec<u64>,
+}
+impl Chain {
+    fn hash(&self, number: u64) -> String {
+        let byte = match self.fork_from {
+            Some(from) if number >= from => 0xf0 | number as u8,
+            _ => number as u8,
+        };
+        format!("0x{}", format!("{:02x}", byte).repeat(32))
+    }
+}
+
+async fn start_chain(chain: Arc<Mutex<Chain>>) -> String {
+    let logs: Vec<Value> = serde_json::from_str(&fixture("logs_sales.json")).unwrap();
+    start_node(Arc::new(move |method, params| {
+        let mut chain = chain.lock().unwrap();
+        match method {
+            "eth_blockNumber" => Ok(json!(format!("0x{:x}", chain.head))),
+            "eth_getLogs" => {
+                let filter = params[0].clone();
+                let (from, to) = (hex(&filter["fromBlock"]), hex(&filter["toBlock"]));
+                chain.filters.push(filter);
+                let logs: Vec<Value> = logs
+                    .iter()
+                    .filter(|log| {
+                        let n = hex(&log["blockNumber"]);
+                        (from..=to).contains(&n) && !chain.orphaned.contains(&n)
+                    })
+                    .map(|log| {
+                        let mut log = log.clone();
+                        log["blockHash"] = json!(chain.hash(hex(&log["blockNumber"])));
+                        log
+                    })
+                    .collect();
+                Ok(json!(logs))
+            }
+            "eth_getBlockByNumber" => {
+                let n = hex(&params[0]);
+                chain.fetched.push(n);
+                match n <= chain.head {
+                    true => Ok(block(n, &chain.hash(n), GENESIS_TS + n * 12)),
+                    false => Ok(Value::Null),
+                }
+            }
+            other => Err(format!("unexpected method {}", other)),
+        }
+    }))
+    .await
+}
+
+fn hex(value: &Value) -> u64 {
+    u64::from_str_radix(value.as_str().unwrap().trim_start_matches("0x"), 16).unwrap()
+}
+
+fn party(n: u8) -> Address {
+    Address::from([n; 20])
+}
+
+fn eth(milli: u64) -> U256 {
+    U256::from(milli) * U256::exp10(15)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn decodes_sales_across_marketplaces() {
+    let chain = Arc::new(Mutex::new(Chain {
+        head: 0x103,
+        ..Chain::default()
+    }));
+    let node = start_chain(chain.clone()).await;
+    let indexer = SaleIndexer::new(&node, CONTRACT).unwrap();
+
+    let sales = indexer.sales(0x100, 0x103).await.unwrap();
+
+    let summary: Vec<(i16, Marketplace, &str, U256, Address, Address)> = sales
+        .iter()
+        .map(|s| {
+            (
+                s.token_id,
+                s.marketplace.clone(),
+                s.payment_token.symbol.as_str(),
+                s.amount,
+                s.seller,
+                s.buyer,
+            )
+        })
+        .collect();
+    // The sale in another collection and the undecodable log are left out.
+    assert_eq!(
+        summary,
+        [
+            (
+                3,
+                Marketplace::OpenSea,
+                "ETH",
+                eth(1_500),
+                party(0x51),
+                party(0xb1)
+            ),
+            (
+                5,
+                Marketplace::OpenSea,
+                "WETH",
+                eth(1_200),
+                party(0x52),
+                party(0xb2)
+            ),
+            (
+                7,
+                Marketplace::LooksRare,
+                "WETH",
+                eth(2_000),
+                party(0x54),
+                party(0xb4)
+            ),
+            (
+                9,
+                Marketplace::X2Y2,
+                "ETH",
+                eth(900),
+                party(0x55),
+                party(0xb5)
+            ),
+        ]
+    );
+    assert_eq!(sales[0].timestamp, GENESIS_TS + 0x100 * 12);
+    assert_eq!(
+        sales[0].order_hash.as_deref(),
+        Some(&*format!("0x{}", "11".repeat(32)))
+    );
+    assert_eq!(sales[2].block_number, 0x102);
+    assert_eq!(sales[2].log_index, 3);
+
+    let filter = &chain.lock().unwrap().filters[0];
+    assert_eq!(filter["fromBlock"], "0x100");
+    assert_eq!(filter["toBlock"], "0x103");
+    assert_eq!(filter["address"].as_array().unwrap().len(), 5);
+    assert_eq!(filter["topics"][0].as_array().unwrap().len(), 4);
+}
+
+fn indexer(node: &str, confirmations: u64, window: usize) -> SaleIndexer {
+    let mut indexer = SaleIndexer::new(node, CONTRACT).unwrap();
+    indexer.set_cursor_options(CursorOptions {
+        confirmations,
+        window,
+        start_block: Some(0x100),
+        ..CursorOptions::default()
+    });
+    indexer
+}
+
+fn token_ids(sales: &[kong_scraper::indexer::SaleRecord]) -> Vec<i16> {
+    sales.iter().map(|s| s.token_id).collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn finalizes_sales_after_confirmations() {
+    let chain = Arc::new(Mutex::new(Chain {
+        head: 0x103,
+        ..Chain::default()
+    }));
+    let node = start_chain(chain.clone()).await;
+    let indexer = indexer(&node, 2, 8);
+    let mut ledger = SaleLedger::default();
+
+    let poll = indexer.poll(&mut ledger).await.unwrap();
+    assert_eq!(
+        poll,
+        Poll {
+            head: 0x103,
+            indexed: 4,
+            finalized: 2,
+            ..Poll::default()
+        }
+    );
+    assert_eq!(token_ids(ledger.finalized()), [3, 5]);
+    assert_eq!(token_ids(ledger.pending()), [7, 9]);
+    assert_eq!(ledger.final_through(), Some(0x101));
+
+    chain.lock().unwrap().head = 0x105;
+    let poll = indexer.poll(&mut ledger).await.unwrap();
+    assert_eq!((poll.indexed, poll.finalized), (0, 2));
+    assert!(ledger.pending().is_empty());
+    assert_eq!(token_ids(&ledger.take_finalized()), [3, 5, 7, 9]);
+    assert!(ledger.finalized().is_empty());
+    assert_eq!(ledger.cursor.next_block(), Some(0x106));
+    // Only the new blocks are asked for.
+    let filter = chain.lock().unwrap().filters[1].clone();
+    assert_eq!(filter["fromBlock"], "0x104");
+    assert_eq!(filter["toBlock"], "0x105");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn catches_up_one_log_range_at_a_time() {
+    let chain = Arc::new(Mutex::new(Chain {
+        head: 0x103,
+        ..Chain::default()
+    }));
+    let node = start_chain(chain.clone()).await;
+    let mut indexer = SaleIndexer::new(&node, CONTRACT).unwrap();
+    indexer.set_cursor_options(CursorOptions {
+        confirmations: 0,
+        window: 2,
+        start_block: Some(0xfe),
+        log_range: 2,
+    });
+    let mut ledger = SaleLedger::default();
+
+    let poll = indexer.poll(&mut ledger).await.unwrap();
+    assert_eq!((poll.indexed, poll.finalized), (4, 4));
+    assert_eq!(token_ids(ledger.finalized()), [3, 5, 7, 9]);
+    assert_eq!(ledger.cursor.next_block(), Some(0x104));
+    let chain = chain.lock().unwrap();
+    let ranges: Vec<(&Value, &Value)> = chain
+        .filters
+        .iter()
+        .map(|f| (&f["fromBlock"], &f["toBlock"]))
+        .collect();
+    assert_eq!(
+        ranges,
+        [
+            (&json!("0xfe"), &json!("0xff")),
+            (&json!("0x100"), &json!("0x101")),
+            (&json!("0x102"), &json!("0x103")),
+        ]
+    );
+    // Blocks inside the window are read once for their hashes and that read
+    // also gives the timestamp. Older blocks are only read if they had a sale.
+    let mut fetched = chain.fetched.clone();
+    fetched.sort_unstable();
+    assert_eq!(fetched, [0x100, 0x101, 0x102, 0x103]);
+    assert_eq!(ledger.finalized()[0].timestamp, GENESIS_TS + 0x100 * 12);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rolls_back_reorged_sales() {
+    let chain = Arc::new(Mutex::new(Chain {
+        head: 0x103,
+        ..Chain::default()
+    }));
+    let node = start_chain(chain.clone()).await;
+    let indexer = indexer(&node, 3, 8);
+    let mut ledger = SaleLedger::default();
+    indexer.poll(&mut ledger).await.unwrap();
+
+    // 0x101 to 0x103 are replaced. The sale in 0x101 makes it onto the new
+    // chain, the two in 0x102 don't.
+    {
+        let mut chain = chain.lock().unwrap();
+        chain.head = 0x104;
+        chain.fork_from = Some(0x101);
+        chain.orphaned = vec![0x102];
+    }
+    let poll = indexer.poll(&mut ledger).await.unwrap();
+    assert_eq!(
+        poll,
+        Poll {
+            head: 0x104,
+            reorg_from: Some(0x101),
+            rolled_back: 3,
+            indexed: 1,
+            finalized: 1,
+        }
+    );
+    assert_eq!(token_ids(ledger.finalized()), [3, 5]);
+    assert!(ledger.pending().is_empty());
+    let hash = chain.lock().unwrap().hash(0x101);
+    assert_eq!(format!("{:?}", ledger.finalized()[1].block_hash), hash);
+    assert_eq!(
+        format!("{:?}", ledger.cursor.hash(0x103).unwrap()),
+        chain.lock().unwrap().hash(0x103)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reorg_deeper_than_window_is_an_error() {
+    let chain = Arc::new(Mutex::new(Chain {
+        head: 0x103,
+        ..Chain::default()
+    }));
+    let node = start_chain(chain.clone()).await;
+    let indexer = indexer(&node, 2, 2);
+    let mut ledger = SaleLedger::default();
+    indexer.poll(&mut ledger).await.unwrap();
+
+    chain.lock().unwrap().fork_from = Some(0x101);
+    let err = indexer.poll(&mut ledger).await.unwrap_err();
+    assert!(matches!(err, Error::ReorgTooDeep { window: 2 }));
+    // Nothing is dropped, so the operator can decide how far to go back.
+    assert_eq!(ledger.pending().len() + ledger.finalized().len(), 4);
+}
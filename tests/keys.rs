@@ -0,0 +1,85 @@
+mod common;
+
+use common::{scraper_with, MockOpensea, Reply};
+use kong_scraper::error::Error;
+use std::time::Duration;
+
+fn token_3(mock: MockOpensea) -> MockOpensea {
+    mock.events(
+        "created",
+        None,
+        vec![Reply::ok("events_created_page2.json")],
+    )
+    .events("successful", None, vec![Reply::ok("events_empty.json")])
+    .events("cancelled", None, vec![Reply::ok("events_empty.json")])
+    .listings(3, vec![Reply::ok("listings_basic.json")])
+}
+
+fn keys(names: &[&str]) -> Vec<String> {
+    names.iter().map(|k| k.to_string()).collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn spreads_requests_across_keys() {
+    let mock = token_3(MockOpensea::new());
+    let (mut bot, _) = scraper_with("key-spread", &mock, |config| {
+        config.os_keys = keys(&["key-a", "key-b"]);
+    })
+    .await;
+
+    bot.update_prices().await.unwrap();
+
+    // Three events queries and one listings request, alternating keys.
+    assert_eq!(mock.key_hits("key-a"), 2);
+    assert_eq!(mock.key_hits("key-b"), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rejected_key_sits_out_cooldown() {
+    let mock = token_3(MockOpensea::new()).reject_key("key-bad", 401);
+    let (mut bot, _) = scraper_with("key-rejected", &mock, |config| {
+        config.os_keys = keys(&["key-bad", "key-good"]);
+    })
+    .await;
+
+    bot.update_prices().await.unwrap();
+
+    assert_eq!(mock.key_hits("key-bad"), 1);
+    assert_eq!(mock.key_hits("key-good"), 4);
+    assert_eq!(bot.get_all().data()[&3].current_sales.len(), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rejected_keys_return_after_cooldown() {
+    let mock = token_3(MockOpensea::new()).reject_key("key-flaky", 401);
+    let (mut bot, _) = scraper_with("key-cooldown", &mock, |config| {
+        config.os_keys = keys(&["key-flaky"]);
+        config.opensea_key_cooldown = Duration::ZERO;
+    })
+    .await;
+
+    let err = bot.update_prices().await.unwrap_err();
+    assert!(matches!(err, Error::Unauthorized { status: 401 }));
+
+    // Once OpenSea takes the key again the next run goes through.
+    mock.accept_key("key-flaky");
+    bot.update_prices().await.unwrap();
+    assert_eq!(mock.key_hits("key-flaky"), 5);
+    assert_eq!(bot.get_all().data()[&3].current_sales.len(), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rate_limited_key_is_benched() {
+    let mock = token_3(MockOpensea::new()).reject_key("key-hot", 429);
+    let (mut bot, _) = scraper_with("key-benched", &mock, |config| {
+        config.os_keys = keys(&["key-hot", "key-cool"]);
+        config.opensea_backoff = Duration::from_secs(30);
+    })
+    .await;
+
+    bot.update_prices().await.unwrap();
+
+    // The long backoff keeps the hot key out for the rest of the run.
+    assert_eq!(mock.key_hits("key-hot"), 1);
+    assert_eq!(mock.key_hits("key-cool"), 4);
+}
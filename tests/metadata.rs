@@ -0,0 +1,168 @@
+mod common;
+
+use common::{scraper_with, start_gateway, start_node, MockOpensea};
+use ethabi::Token;
+use kong_scraper::{
+    error::Error,
+    kong_data::KongTraits,
+    metadata::{changed_tokens, write_metadata_json, MetadataFetcher, TokenMetadata},
+    reader::{ContractReader, TokenReader},
+    utils::get_defaults,
+};
+use serde_json::json;
+use std::{collections::HashMap, env, fs, sync::Arc};
+
+// Token URIs the way the contract hands them out. Token 3's file is missing
+// from the gateway and token 4's URI reverts.
+fn token_uri(id: u64, gateway: &str) -> Option<String> {
+    match id {
+        0 => Some(String::from("ipfs://QmKongs/0")),
+        1 => Some(format!("{}/kongs/1.json", gateway)),
+        2 => Some(String::from("ipfs://ipfs/QmKongs/2")),
+        3 => Some(String::from("ipfs://QmKongs/3")),
+        _ => None,
+    }
+}
+
+async fn start_chain(gateway: String) -> String {
+    start_node(Arc::new(move |method, params| match method {
+        "eth_blockNumber" => Ok(json!("0x64")),
+        "eth_call" => {
+            let data = params[0]["data"].as_str().unwrap();
+            let data = hex::decode(data.trim_start_matches("0x")).unwrap();
+            let id = ethabi::decode(&[ethabi::ParamType::Uint(256)], &data[4..]).unwrap()[0]
+                .clone()
+                .into_uint()
+                .unwrap()
+                .as_u64();
+            match token_uri(id, &gateway) {
+                Some(uri) => Ok(json!(format!(
+                    "0x{}",
+                    hex::encode(ethabi::encode(&[Token::String(uri)]))
+                ))),
+                None => Err(String::from("execution reverted")),
+            }
+        }
+        other => Err(format!("unexpected method {}", other)),
+    }))
+    .await
+}
+
+async fn fetch(ids: &[i16]) -> (MetadataFetcher, HashMap<i16, KongTraits>) {
+    let gateway = start_gateway("tests/fixtures/metadata").await;
+    let node = start_chain(gateway.clone()).await;
+    let tokens = TokenReader::new(ContractReader::new(&[node]).unwrap()).unwrap();
+    let fetcher = MetadataFetcher::new(&gateway).unwrap();
+
+    let uris = tokens.token_uris(ids, 100).await.unwrap();
+    assert_eq!(uris[4], None);
+    let traits = fetcher
+        .fetch_traits(
+            ids.iter()
+                .zip(uris)
+                .filter_map(|(id, uri)| uri.map(|u| (*id, u)))
+                .collect(),
+        )
+        .await;
+    (fetcher, traits)
+}
+
+#[test]
+fn resolves_token_uris() {
+    let fetcher = MetadataFetcher::new("https://gateway.test/").unwrap();
+
+    assert_eq!(
+        fetcher.resolve("ipfs://QmKongs/1").unwrap(),
+        "https://gateway.test/ipfs/QmKongs/1"
+    );
+    assert_eq!(
+        fetcher.resolve("ipfs://ipfs/QmKongs/1").unwrap(),
+        "https://gateway.test/ipfs/QmKongs/1"
+    );
+    assert_eq!(
+        fetcher.resolve("https://api.test/kongs/1").unwrap(),
+        "https://api.test/kongs/1"
+    );
+    assert!(matches!(
+        fetcher.resolve("ar://kongs/1"),
+        Err(Error::Metadata(_))
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn loads_traits_from_token_uris() {
+    let (_, traits) = fetch(&[0, 1, 2, 3, 4]).await;
+    let defaults = get_defaults().unwrap();
+
+    // 3 isn't on the gateway and 4 has no URI, so neither is there.
+    let mut ids: Vec<i16> = traits.keys().copied().collect();
+    ids.sort_unstable();
+    assert_eq!(ids, [0, 1, 2]);
+    // Missing cumulative is summed from the stats, and "None" means no trait.
+    assert_eq!(traits[&0], defaults[&0].traits);
+    assert_eq!(traits[&1], defaults[&1].traits);
+    assert_eq!(traits[&2].clothes.as_deref(), Some("Red Kimono"));
+    assert_eq!(changed_tokens(&defaults, &traits), [2]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn regenerates_metadata_json() {
+    let (_, traits) = fetch(&[0, 1, 2, 3, 4]).await;
+    let path = env::temp_dir().join(format!("kong-metadata-{}.json", std::process::id()));
+    let path = path.to_string_lossy().to_string();
+
+    write_metadata_json(&path, &traits).unwrap();
+    let written = fs::read_to_string(&path).unwrap();
+    let read_back: HashMap<i16, KongTraits> = serde_json::from_str(&written).unwrap();
+    assert_eq!(read_back, traits);
+    assert!(written.find("\"0\"").unwrap() < written.find("\"2\"").unwrap());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn rejects_incomplete_metadata() {
+    let metadata: TokenMetadata = serde_json::from_value(json!({
+        "name": "Kong #9",
+        "attributes": [
+            { "trait_type": "Background", "value": "Gold" },
+            { "trait_type": "Shooting", "value": 300 },
+        ],
+    }))
+    .unwrap();
+
+    let err = metadata.traits().unwrap_err();
+    assert!(matches!(err, Error::Metadata(_)));
+    assert!(!err.is_retryable());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failed_refresh_is_retried_next_cycle() {
+    // The gateway serves nothing until the files are copied in.
+    let root = env::temp_dir().join(format!("kong-gateway-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+    let gateway = start_gateway(&root.to_string_lossy()).await;
+    let node = start_chain(gateway.clone()).await;
+    let (mut bot, _) = scraper_with("metadata-retry", &MockOpensea::new(), |config| {
+        config.node_url = node;
+        config.ipfs_gateway = gateway;
+        config.refresh_metadata = true;
+    })
+    .await;
+
+    let err = bot.update_metadata().await.unwrap_err();
+    assert!(matches!(err, Error::Metadata(_)));
+
+    for file in ["ipfs/QmKongs/0", "ipfs/QmKongs/2", "kongs/1.json"] {
+        let to = root.join(file);
+        fs::create_dir_all(to.parent().unwrap()).unwrap();
+        fs::copy(format!("tests/fixtures/metadata/{}", file), to).unwrap();
+    }
+    bot.update_metadata().await.unwrap();
+    fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!(
+        bot.get_all().data()[&2].traits.clothes.as_deref(),
+        Some("Red Kimono")
+    );
+}
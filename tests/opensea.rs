@@ -0,0 +1,302 @@
+mod common;
+
+use common::{scraper, scraper_with, MockOpensea, Reply};
+use kong_scraper::{
+    error::Error,
+    kong_data::{Cached, Sale},
+    opensea_client::breaker::CircuitBreaker,
+};
+use serde_json::json;
+use std::{path::Path, time::Duration};
+
+fn quiet_events(mock: MockOpensea) -> MockOpensea {
+    mock.events("successful", None, vec![Reply::ok("events_empty.json")])
+        .events("cancelled", None, vec![Reply::ok("events_empty.json")])
+}
+
+// Events that only mark token 3 as changed, with the given listings replies for it.
+fn token_3(replies: Vec<Reply>) -> MockOpensea {
+    let mock = MockOpensea::new().events(
+        "created",
+        None,
+        vec![Reply::ok("events_created_page2.json")],
+    );
+    quiet_events(mock).listings(3, replies)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn updates_prices_across_paginated_events() {
+    let mock = MockOpensea::new()
+        .events(
+            "created",
+            Some("cursor-2"),
+            vec![Reply::ok("events_created_page2.json")],
+        )
+        .events(
+            "created",
+            None,
+            vec![Reply::ok("events_created_page1.json")],
+        );
+    let mock = quiet_events(mock)
+        .listings(1, vec![Reply::ok("listings_basic.json")])
+        .listings(2, vec![Reply::ok("listings_basic.json")])
+        .listings(3, vec![Reply::ok("listings_basic.json")]);
+    let (mut bot, config) = scraper("paginated", &mock).await;
+
+    bot.update_prices().await.unwrap();
+
+    let data = bot.get_all().data();
+    for id in [1, 2, 3] {
+        let sales = &data[&id].current_sales;
+        assert_eq!(sales.len(), 2);
+        assert_eq!(sales[0].price_eth(), 1.5);
+        assert_eq!(sales[1].price_eth(), 2.0);
+        assert_eq!(mock.listing_hits(id), 1);
+    }
+    assert!(data[&4].current_sales.is_empty());
+    assert_eq!(mock.hits("/api/v1/events"), 4);
+    assert!(Path::new(&config.cache_path).exists());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sorts_listings_cheapest_first() {
+    let mock = token_3(vec![Reply::ok("listings_unsorted.json")]);
+    let (mut bot, _) = scraper("unsorted", &mock).await;
+
+    bot.update_prices().await.unwrap();
+
+    let prices: Vec<f64> = bot.get_all().data()[&3]
+        .current_sales
+        .iter()
+        .map(|s| s.price_eth())
+        .collect();
+    assert_eq!(prices, [2.0, 3.0]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn retries_through_429_burst() {
+    let mock = token_3(vec![
+        Reply::status(429),
+        Reply::status(429),
+        Reply::status(429),
+        Reply::ok("listings_basic.json"),
+    ]);
+    let (mut bot, _) = scraper("burst", &mock).await;
+
+    bot.update_prices().await.unwrap();
+
+    assert_eq!(mock.listing_hits(3), 4);
+    assert_eq!(bot.get_all().data()[&3].current_sales.len(), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn retries_through_transient_server_errors() {
+    let mock = token_3(vec![
+        Reply::status(502),
+        Reply::status(503),
+        Reply::ok("listings_basic.json"),
+    ]);
+    let (mut bot, _) = scraper("transient-5xx", &mock).await;
+
+    bot.update_prices().await.unwrap();
+
+    assert_eq!(mock.listing_hits(3), 3);
+    assert_eq!(bot.get_all().data()[&3].current_sales.len(), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn server_error_aborts_update() {
+    let mock = token_3(vec![Reply::status(502)]);
+    let (mut bot, config) = scraper("server-error", &mock).await;
+
+    let err = bot.update_prices().await.unwrap_err();
+    assert!(matches!(err, Error::HttpStatus { status: 502, .. }));
+    assert!(err.is_retryable());
+    assert_eq!(
+        mock.listing_hits(3),
+        config.opensea_max_retries as usize + 1
+    );
+    assert!(bot.get_all().data()[&3].current_sales.is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn missing_token_has_no_listings() {
+    let mock = token_3(vec![Reply::status(404)]);
+    let (mut bot, _) = scraper("not-found", &mock).await;
+
+    bot.update_prices().await.unwrap();
+
+    assert_eq!(mock.listing_hits(3), 1);
+    assert!(bot.get_all().data()[&3].current_sales.is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rejected_key_fails_fast() {
+    let mock = token_3(vec![Reply::status(401)]);
+    let (mut bot, _) = scraper("unauthorized", &mock).await;
+
+    let err = bot.update_prices().await.unwrap_err();
+    assert!(matches!(err, Error::Unauthorized { status: 401 }));
+    assert_eq!(mock.listing_hits(3), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn circuit_opens_after_sustained_failures() {
+    let mock = token_3(vec![Reply::status(500)]);
+    let (mut bot, _) = scraper_with("breaker", &mock, |config| {
+        config.opensea_max_retries = 5;
+        config.opensea_breaker_threshold = 2;
+    })
+    .await;
+
+    let err = bot.update_prices().await.unwrap_err();
+    assert!(matches!(err, Error::CircuitOpen { failures: 2 }));
+    assert_eq!(mock.listing_hits(3), 2);
+}
+
+#[test]
+fn half_open_circuit_lets_one_probe_through() {
+    let breaker = CircuitBreaker::new(1, Duration::from_millis(50));
+    breaker.record_failure();
+    assert!(breaker.check().is_err());
+
+    std::thread::sleep(Duration::from_millis(60));
+    assert!(breaker.check().is_ok());
+    // Held back while the probe is out.
+    assert!(breaker.check().is_err());
+
+    // A failed probe reopens it for the whole cooldown.
+    breaker.record_failure();
+    assert!(breaker.check().is_err());
+    std::thread::sleep(Duration::from_millis(60));
+    assert!(breaker.check().is_ok());
+    breaker.record_success();
+    assert!(breaker.check().is_ok());
+    assert!(breaker.check().is_ok());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn slow_response_times_out() {
+    let mock = token_3(vec![
+        Reply::ok("listings_basic.json").delayed(Duration::from_secs(5))
+    ]);
+    let (mut bot, _) = scraper_with("timeout", &mock, |config| {
+        config.opensea_timeout = Duration::from_millis(200);
+        config.opensea_max_retries = 0;
+    })
+    .await;
+
+    let err = bot.update_prices().await.unwrap_err();
+    assert!(matches!(err, Error::Timeout));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn malformed_events_payload_is_an_error() {
+    let mock = MockOpensea::new().events("created", None, vec![Reply::ok("events_malformed.json")]);
+    let (mut bot, _) = scraper("malformed", &quiet_events(mock)).await;
+
+    let err = bot.update_prices().await.unwrap_err();
+    assert!(matches!(err, Error::Decode { .. }));
+    assert!(!err.is_retryable());
+    assert_eq!(bot.get_all().num_listed(), 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn expired_listings_are_pruned() {
+    let mock = token_3(vec![Reply::ok("listings_expired.json")]);
+    let (mut bot, _) = scraper("expired", &mock).await;
+
+    bot.update_prices().await.unwrap();
+
+    let sales = &bot.get_all().data()[&3].current_sales;
+    assert_eq!(sales.len(), 1);
+    assert_eq!(sales[0].price_eth(), 2.0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn order_in_both_lists_is_kept_once() {
+    let mock = token_3(vec![Reply::ok("listings_duplicate.json")]);
+    let (mut bot, _) = scraper("duplicate", &mock).await;
+
+    bot.update_prices().await.unwrap();
+
+    assert_eq!(bot.get_all().data()[&3].current_sales.len(), 1);
+}
+
+// Lists token 3 on the first update. On the second its successful and cancelled
+// events are served from the given fixtures and its listings can't be fetched.
+fn relisted_then(successful: &str, cancelled: &str) -> MockOpensea {
+    let first_then = |second: &str| vec![Reply::ok("events_empty.json"), Reply::ok(second)];
+    MockOpensea::new()
+        .events(
+            "created",
+            None,
+            vec![
+                Reply::ok("events_created_page2.json"),
+                Reply::ok("events_empty.json"),
+            ],
+        )
+        .events("successful", None, first_then(successful))
+        .events("cancelled", None, first_then(cancelled))
+        .listings(
+            3,
+            vec![Reply::ok("listings_basic.json"), Reply::status(400)],
+        )
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn cancelled_event_removes_matching_order() {
+    let mock = relisted_then("events_empty.json", "events_cancelled.json");
+    let (mut bot, _) = scraper("cancelled", &mock).await;
+    bot.update_prices().await.unwrap();
+
+    assert!(bot.update_prices().await.is_err());
+
+    let sales = &bot.get_all().data()[&3].current_sales;
+    assert_eq!(sales.len(), 1);
+    assert_eq!(
+        sales[0].order_hash.as_deref(),
+        Some(&*format!("0x{}", "22".repeat(32)))
+    );
+}
+
+#[test]
+fn cancel_matches_order_hash_in_any_case() {
+    let listing = |hash: &str| -> Sale {
+        serde_json::from_value(json!({
+            "order_hash": hash,
+            "created_timestamp": 1659355200,
+            "expiration_timestamp": null,
+            "sale_type": "BuyNow",
+            "price_wei": "1000000000000000000",
+            "price_usd": null,
+            "platform": "OpenSea"
+        }))
+        .unwrap()
+    };
+    let mut cached = Cached::new().unwrap();
+    cached.data_mut().get_mut(&3).unwrap().current_sales = vec![
+        listing(&format!("0x{}", "ab".repeat(32))),
+        listing(&format!("0x{}", "cd".repeat(32))),
+    ];
+
+    cached.remove_order(3, &format!("0x{}", "AB".repeat(32)));
+
+    let sales = &cached.data()[&3].current_sales;
+    assert_eq!(sales.len(), 1);
+    assert_eq!(
+        sales[0].order_hash.as_deref(),
+        Some(&*format!("0x{}", "cd".repeat(32)))
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn successful_event_clears_token() {
+    let mock = relisted_then("events_successful.json", "events_empty.json");
+    let (mut bot, _) = scraper("successful", &mock).await;
+    bot.update_prices().await.unwrap();
+
+    assert!(bot.update_prices().await.is_err());
+
+    assert!(bot.get_all().data()[&3].current_sales.is_empty());
+}
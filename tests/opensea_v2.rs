@@ -0,0 +1,213 @@
+mod common;
+
+use common::{fixture, scraper_with, MockOpensea, Reply};
+use kong_scraper::{
+    kong_data::{Auction, AuctionKind, ScaperBot},
+    opensea_client::{v2::CollectionListing, ApiVersion, OrdersResponse, PriceStrategy},
+    utils::get_current_ts,
+};
+use web3::types::U256;
+
+const ETH: u64 = 1_000_000_000_000_000_000;
+
+const LISTINGS: &str = "/api/v2/orders/ethereum/seaport/listings";
+const EVENTS: &str = "/api/v2/events/collection/rumble-kong-league";
+
+fn v2_mock() -> MockOpensea {
+    MockOpensea::new()
+        .route(
+            EVENTS,
+            &[("event_type", "listing")],
+            vec![Reply::ok("v2_events_listing.json")],
+        )
+        .route(EVENTS, &[], vec![Reply::ok("v2_events_empty.json")])
+        .route(
+            LISTINGS,
+            &[("token_ids", "3"), ("cursor", "cursor-2")],
+            vec![Reply::ok("v2_listings_page2.json")],
+        )
+        .route(
+            LISTINGS,
+            &[("token_ids", "3")],
+            vec![Reply::ok("v2_listings_page1.json")],
+        )
+        .route(
+            LISTINGS,
+            &[("token_ids", "5")],
+            vec![Reply::ok("v2_listings_empty.json")],
+        )
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn updates_prices_with_v2_api() {
+    let mock = v2_mock();
+    let (mut bot, _) = scraper_with("v2", &mock, |config| {
+        config.opensea_api = ApiVersion::V2;
+    })
+    .await;
+
+    bot.update_prices().await.unwrap();
+
+    let data = bot.get_all().data();
+    // The cancelled order on page two is dropped and the rest sorted cheapest first.
+    let sales = &data[&3].current_sales;
+    assert_eq!(sales.len(), 2);
+    assert_eq!(sales[0].price_eth(), 1.5);
+    assert_eq!(sales[1].price_eth(), 2.0);
+    assert!(data[&5].current_sales.is_empty());
+    assert_eq!(mock.hits(LISTINGS), 3);
+    assert_eq!(mock.hits(EVENTS), 3);
+    assert_eq!(mock.hits("/api/v1/events"), 0);
+}
+
+#[test]
+fn preserves_seaport_order_parameters() {
+    let res: OrdersResponse = serde_json::from_str(&fixture("v2_listings_page1.json")).unwrap();
+    let order = &res.orders[0];
+    let params = &order.protocol_data.parameters;
+
+    assert_eq!(params.offer[0].identifier_or_criteria, "3");
+    assert_eq!(params.consideration.len(), 3);
+    assert_eq!(params.total_original_consideration_items, 3);
+    assert_eq!(params.zone, "0x004c00500000ad104d7dbd00e3ae0a5c00560c00");
+    assert!(params.salt.starts_with("0x360c6ebe"));
+    assert_eq!(params.end_time, 4_102_444_800);
+    assert_eq!(order.maker_fees[0].basis_points, 250);
+    // Marketplace and creator fees, but not the seller's proceeds.
+    let fees: Vec<&str> = order
+        .fee_items()
+        .iter()
+        .map(|item| item.start_amount.as_str())
+        .collect();
+    assert_eq!(fees, ["50000000000000000", "100000000000000000"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn bulk_refresh_reconciles_every_token() {
+    let bulk = "/api/v2/listings/collection/rumble-kong-league/all";
+    let mock = MockOpensea::new()
+        .events(
+            "created",
+            None,
+            vec![Reply::ok("events_created_page2.json")],
+        )
+        .events("successful", None, vec![Reply::ok("events_empty.json")])
+        .events("cancelled", None, vec![Reply::ok("events_empty.json")])
+        .listings(3, vec![Reply::ok("listings_basic.json")])
+        .route(
+            bulk,
+            &[("next", "cursor-2")],
+            vec![Reply::ok("v2_collection_listings_page2.json")],
+        )
+        .route(
+            bulk,
+            &[],
+            vec![Reply::ok("v2_collection_listings_page1.json")],
+        );
+    let (mut bot, mut config) = scraper_with("bulk", &mock, |_| {}).await;
+    bot.update_prices().await.unwrap();
+    assert_eq!(bot.get_all().data()[&3].current_sales.len(), 2);
+
+    // Same cache, switched over to the bulk strategy.
+    config.price_strategy = PriceStrategy::Bulk;
+    let mut bot = ScaperBot::init(&config).await.unwrap();
+    bot.update_prices().await.unwrap();
+
+    let data = bot.get_all().data();
+    assert!(data[&3].current_sales.is_empty());
+    let prices: Vec<f64> = data[&7]
+        .current_sales
+        .iter()
+        .map(|s| s.price_eth())
+        .collect();
+    assert_eq!(prices, [1.2, 2.0]);
+    assert_eq!(data[&9].current_sales[0].price_eth(), 1.0);
+    assert_eq!(bot.get_all().num_listed(), 2);
+    assert_eq!(mock.hits(bulk), 2);
+}
+
+#[test]
+fn dutch_auction_declines_linearly() {
+    let auction = Auction {
+        kind: AuctionKind::Dutch,
+        start_amount: U256::from(3),
+        end_amount: U256::from(1),
+        start_time: 100,
+        end_time: 103,
+    };
+    let prices: Vec<U256> = [50, 100, 101, 102, 103, 200]
+        .iter()
+        .map(|ts| auction.amount_at(*ts))
+        .collect();
+    // 2.33 and 1.67 round up, as Seaport settles them.
+    assert_eq!(prices, [3, 3, 3, 2, 1, 1].map(U256::from));
+
+    let english = Auction {
+        kind: AuctionKind::English,
+        ..auction
+    };
+    assert_eq!(english.amount_at(102), U256::from(3));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn dutch_auction_priced_at_update_time() {
+    let mock = MockOpensea::new()
+        .route(
+            EVENTS,
+            &[("event_type", "listing")],
+            vec![Reply::ok("v2_events_listing.json")],
+        )
+        .route(EVENTS, &[], vec![Reply::ok("v2_events_empty.json")])
+        .route(
+            LISTINGS,
+            &[("token_ids", "3")],
+            vec![Reply::ok("v2_listings_dutch.json")],
+        )
+        .route(
+            LISTINGS,
+            &[("token_ids", "5")],
+            vec![Reply::ok("v2_listings_empty.json")],
+        );
+    let (mut bot, _) = scraper_with("dutch", &mock, |config| {
+        config.opensea_api = ApiVersion::V2;
+    })
+    .await;
+
+    let before = get_current_ts();
+    bot.update_prices().await.unwrap();
+    let after = get_current_ts();
+
+    // The auction started at 2 ETH and has since dropped below the 1.9 ETH listing.
+    let sales = &bot.get_all().data()[&3].current_sales;
+    let auction = sales[0]
+        .auction
+        .as_ref()
+        .expect("first listing is the auction");
+    assert_eq!(auction.start_amount, U256::from(2 * ETH));
+    assert_eq!(auction.end_amount, U256::from(ETH));
+    assert!(sales[0].price_wei <= auction.amount_at(before));
+    assert!(sales[0].price_wei >= auction.amount_at(after));
+    assert_eq!(sales[1].price_wei, U256::from(ETH) * 19 / 10);
+    assert!(sales[1].auction.is_none());
+}
+
+#[test]
+fn payment_token_comes_from_the_order() {
+    let fake_weth = "0x5555555555555555555555555555555555555555";
+    let page: serde_json::Value =
+        serde_json::from_str(&fixture("v2_collection_listings_page1.json")).unwrap();
+    let mut listing = page["listings"][0].clone();
+    listing["price"]["current"]["currency"] = "WETH".into();
+    for item in listing["protocol_data"]["parameters"]["consideration"]
+        .as_array_mut()
+        .unwrap()
+    {
+        item["token"] = fake_weth.into();
+    }
+    let listing: CollectionListing = serde_json::from_value(listing).unwrap();
+
+    // OpenSea calls it WETH, but it isn't the WETH contract.
+    let token = listing.payment_token();
+    assert_eq!(token.address, fake_weth);
+    assert!(!token.is_eth());
+}
@@ -0,0 +1,274 @@
+mod common;
+
+use common::{scraper_with, start_node, MockOpensea, Reply};
+use ethabi::Token;
+use kong_scraper::{
+    error::Error,
+    indexer::SaleRecord,
+    kong_data::Marketplace,
+    oracle::{fill_sale_usd, Chainlink, PriceOracle},
+    payment::PaymentToken,
+};
+use serde_json::Value;
+use std::{
+    fs::File,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+use web3::types::{Address, H256, U256};
+
+const FEED: &str = "0x5f4eC3Df9cbd43714FE2740f5E3616155c5b8419";
+const BASE_TS: u64 = 1_599_998_400;
+// Rounds land half past the hour, so answering from the start of the hour
+// would pick the round before.
+const ROUND_OFFSET: u64 = 1_800;
+const PHASE: u128 = 2;
+const LATEST_ROUND: u128 = 1_000;
+
+fn round_ts(n: u64) -> u64 {
+    BASE_TS + n * 3_600 + ROUND_OFFSET
+}
+
+// Round n of the mock feed is updated at round_ts(n) at $1000 + $n.
+fn round(id: u128) -> Vec<Token> {
+    let n = id & u128::from(u64::MAX);
+    round_with(n, U256::from((1_000 + n) * 100_000_000))
+}
+
+fn round_with(n: u128, answer: U256) -> Vec<Token> {
+    vec![
+        Token::Uint(U256::from((PHASE << 64) | n)),
+        Token::Int(answer),
+        Token::Uint(U256::from(round_ts(n as u64))),
+        Token::Uint(U256::from(round_ts(n as u64))),
+        Token::Uint(U256::from((PHASE << 64) | n)),
+    ]
+}
+
+fn answer(params: &Value) -> String {
+    answer_with(params, round)
+}
+
+fn answer_with(params: &Value, round: fn(u128) -> Vec<Token>) -> String {
+    let abi =
+        ethabi::Contract::load(File::open("src/utils/chainlink_aggregator_abi.json").unwrap())
+            .unwrap();
+    let data = hex::decode(params[0]["data"].as_str().unwrap().trim_start_matches("0x")).unwrap();
+    let func = abi
+        .functions()
+        .find(|f| f.short_signature() == data[..4])
+        .unwrap();
+    let out = match func.name.as_str() {
+        "decimals" => vec![Token::Uint(U256::from(8))],
+        "latestRoundData" => round(LATEST_ROUND),
+        "getRoundData" => {
+            let id = func.decode_input(&data[4..]).unwrap()[0]
+                .clone()
+                .into_uint()
+                .unwrap()
+                .low_u128();
+            round(id)
+        }
+        other => panic!("unexpected call {}", other),
+    };
+    format!("0x{}", hex::encode(ethabi::encode(&out)))
+}
+
+// A JSON-RPC node that only knows the aggregator calls.
+async fn start_feed(calls: Arc<AtomicUsize>) -> String {
+    start_node(Arc::new(move |method, params| {
+        calls.fetch_add(1, Ordering::SeqCst);
+        match method {
+            "eth_call" => Ok(Value::String(answer(params))),
+            other => Err(format!("unexpected method {}", other)),
+        }
+    }))
+    .await
+}
+
+fn sale(token: PaymentToken, amount: U256, timestamp: u64) -> SaleRecord {
+    SaleRecord {
+        token_id: 1,
+        marketplace: Marketplace::OpenSea,
+        order_hash: None,
+        seller: Address::zero(),
+        buyer: Address::zero(),
+        amount,
+        payment_token: token,
+        block_number: 1,
+        block_hash: H256::zero(),
+        timestamp,
+        tx_hash: H256::zero(),
+        log_index: 0,
+        price_usd: None,
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reads_latest_round() {
+    let node = start_feed(Arc::new(AtomicUsize::new(0))).await;
+    let feed = Chainlink::new(&node, FEED).unwrap();
+
+    assert_eq!(feed.eth_usd().await.unwrap(), 2_000.0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn finds_historical_round_and_caches_it() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let node = start_feed(calls.clone()).await;
+    let feed = Chainlink::new(&node, FEED).unwrap();
+
+    let ts = round_ts(500) + 600;
+    assert_eq!(feed.eth_usd_at(ts).await.unwrap(), 1_500.0);
+    let after_search = calls.load(Ordering::SeqCst);
+    // Still between the same two rounds, answered from the cache.
+    assert_eq!(feed.eth_usd_at(round_ts(501) - 1).await.unwrap(), 1_500.0);
+    assert_eq!(calls.load(Ordering::SeqCst), after_search);
+
+    // Within the hour the round in effect at that second is used.
+    assert_eq!(feed.eth_usd_at(BASE_TS + 7 * 3_600).await.unwrap(), 1_006.0);
+    assert_eq!(feed.eth_usd_at(round_ts(7)).await.unwrap(), 1_007.0);
+    // Before the phase started there's no round to answer with.
+    assert!(matches!(
+        feed.eth_usd_at(BASE_TS).await,
+        Err(Error::NoPriceHistory(BASE_TS))
+    ));
+    assert_eq!(feed.eth_usd_at(u64::MAX / 2).await.unwrap(), 2_000.0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rejects_non_positive_answers() {
+    let node = start_node(Arc::new(|method, params| match method {
+        "eth_call" => Ok(Value::String(answer_with(params, |n| {
+            round_with(n, !U256::from(100_000_000) + 1)
+        }))),
+        other => Err(format!("unexpected method {}", other)),
+    }))
+    .await;
+    let feed = Chainlink::new(&node, FEED).unwrap();
+
+    assert!(feed.eth_usd().await.is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn prices_sales_at_their_time() {
+    let node = start_feed(Arc::new(AtomicUsize::new(0))).await;
+    let feed = Chainlink::new(&node, FEED).unwrap();
+    let eth = U256::from(10).pow(18.into());
+    let mut sales = vec![
+        sale(PaymentToken::eth(), eth * 2, round_ts(500) + 60),
+        sale(PaymentToken::eth(), eth, round_ts(20)),
+        sale(
+            PaymentToken::from_symbol("USDC").unwrap(),
+            U256::from(2_500_000_000_u64),
+            round_ts(20),
+        ),
+        sale(PaymentToken::resolve(FEED), eth, round_ts(20)),
+    ];
+
+    fill_sale_usd(&feed, &mut sales).await;
+
+    let usd: Vec<Option<f64>> = sales.iter().map(|s| s.price_usd).collect();
+    assert_eq!(usd, [Some(3_000.0), Some(1_020.0), Some(2_500.0), None]);
+}
+
+// The current phase's rounds are `round`. Phase 1 ran hourly for the 100
+// hours before it at $500 + $n.
+fn two_phases(id: u128) -> Vec<Token> {
+    let n = id & u128::from(u64::MAX);
+    match id >> 64 {
+        1 => {
+            let ts = U256::from(BASE_TS - 100 * 3_600 + n as u64 * 3_600);
+            vec![
+                Token::Uint(U256::from(id)),
+                Token::Int(U256::from((500 + n) * 100_000_000)),
+                Token::Uint(ts),
+                Token::Uint(ts),
+                Token::Uint(U256::from(id)),
+            ]
+        }
+        _ => round(id),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sales_before_the_current_phase_have_no_usd_price() {
+    let node = start_node(Arc::new(|method, params| match method {
+        "eth_call" => Ok(Value::String(answer_with(params, two_phases))),
+        other => Err(format!("unexpected method {}", other)),
+    }))
+    .await;
+    let feed = Chainlink::new(&node, FEED).unwrap();
+    let eth = U256::from(10).pow(18.into());
+    // Half way through phase 1, whose price was $550 then.
+    let in_phase_1 = BASE_TS - 50 * 3_600 + 60;
+    let mut sales = vec![
+        sale(PaymentToken::eth(), eth, in_phase_1),
+        sale(PaymentToken::eth(), eth, round_ts(20)),
+    ];
+
+    assert!(matches!(
+        feed.eth_usd_at(in_phase_1).await,
+        Err(Error::NoPriceHistory(_))
+    ));
+    fill_sale_usd(&feed, &mut sales).await;
+
+    let usd: Vec<Option<f64>> = sales.iter().map(|s| s.price_usd).collect();
+    assert_eq!(usd, [None, Some(1_020.0)]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fills_usd_prices_for_listings() {
+    let mock = MockOpensea::new()
+        .events(
+            "created",
+            None,
+            vec![Reply::ok("events_created_page2.json")],
+        )
+        .events("successful", None, vec![Reply::ok("events_empty.json")])
+        .events("cancelled", None, vec![Reply::ok("events_empty.json")])
+        .listings(3, vec![Reply::ok("listings_basic.json")]);
+    let (mut bot, _) = scraper_with("usd", &mock, |config| {
+        config.eth_usd_fixed = Some(1_600.0);
+    })
+    .await;
+
+    bot.update_prices().await.unwrap();
+
+    let usd: Vec<Option<f64>> = bot.get_all().data()[&3]
+        .current_sales
+        .iter()
+        .map(|s| s.price_usd)
+        .collect();
+    assert_eq!(usd, [Some(2_400.0), Some(3_200.0)]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn normalizes_token_listings_to_eth() {
+    let mock = MockOpensea::new()
+        .events(
+            "created",
+            None,
+            vec![Reply::ok("events_created_page2.json")],
+        )
+        .events("successful", None, vec![Reply::ok("events_empty.json")])
+        .events("cancelled", None, vec![Reply::ok("events_empty.json")])
+        .listings(3, vec![Reply::ok("listings_tokens.json")]);
+    let (mut bot, _) = scraper_with("tokens", &mock, |config| {
+        config.eth_usd_fixed = Some(1_600.0);
+    })
+    .await;
+
+    bot.update_prices().await.unwrap();
+
+    // The USDC listing is worth one ETH. The unknown token and the one only
+    // calling itself WETH have no rate and are skipped.
+    let sales = &bot.get_all().data()[&3].current_sales;
+    assert_eq!(sales.len(), 1);
+    assert_eq!(sales[0].payment_token.symbol, "USDC");
+    assert_eq!(sales[0].amount, 1_600_000_000_u64.into());
+    assert_eq!(sales[0].price_eth(), 1.0);
+    assert_eq!(sales[0].price_usd, Some(1_600.0));
+}
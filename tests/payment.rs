@@ -0,0 +1,76 @@
+use kong_scraper::payment::{
+    format_units, parse_amount, parse_units, AmountError, PaymentToken, Rates,
+};
+use web3::types::U256;
+
+fn wei(s: &str) -> U256 {
+    U256::from_dec_str(s).unwrap()
+}
+
+#[test]
+fn parses_amounts_exactly() {
+    assert_eq!(parse_units("1.5", 18), Ok(wei("1500000000000000000")));
+    assert_eq!(parse_units(".25", 6), Ok(wei("250000")));
+    assert_eq!(
+        parse_amount("1500000000000000000.0"),
+        Ok(wei("1500000000000000000"))
+    );
+    // Beyond what an f64 holds exactly.
+    assert_eq!(
+        parse_amount("123456789012345678901234567"),
+        Ok(wei("123456789012345678901234567"))
+    );
+}
+
+#[test]
+fn rejects_malformed_amounts() {
+    assert_eq!(parse_amount(""), Err(AmountError::Empty));
+    assert_eq!(parse_amount("1e18"), Err(AmountError::InvalidDigit('e')));
+    assert_eq!(parse_amount("-1"), Err(AmountError::InvalidDigit('-')));
+    assert_eq!(parse_amount("1.5"), Err(AmountError::TooPrecise(0)));
+    assert_eq!(parse_amount(&"9".repeat(80)), Err(AmountError::Overflow));
+}
+
+#[test]
+fn formats_units_without_trailing_zeros() {
+    assert_eq!(format_units(wei("1500000000000000000"), 18), "1.5");
+    assert_eq!(format_units(wei("1"), 18), "0.000000000000000001");
+    assert_eq!(format_units(wei("2000000"), 6), "2");
+    assert_eq!(format_units(U256::zero(), 18), "0");
+}
+
+#[test]
+fn converts_amounts_to_wei() {
+    let mut rates = Rates::new(Some(1_600.0));
+    let usdc = PaymentToken::from_symbol("USDC").unwrap();
+    let weth = PaymentToken::from_symbol("WETH").unwrap();
+    let ape = PaymentToken::from_symbol("APE").unwrap();
+    rates.set_usd(&usdc, 1.0);
+
+    let odd = wei("1234567890123456789");
+    assert_eq!(rates.to_wei(&weth, odd), Some(odd));
+    assert_eq!(
+        rates.to_wei(&usdc, wei("1600000000")),
+        Some(wei("1000000000000000000"))
+    );
+    assert_eq!(rates.to_wei(&ape, wei("1000000000000000000")), None);
+}
+
+#[test]
+fn only_the_weth_contract_counts_as_eth() {
+    let fake = PaymentToken {
+        address: String::from("0x5555555555555555555555555555555555555555"),
+        symbol: String::from("WETH"),
+        decimals: 18,
+    };
+    let weth = PaymentToken::resolve("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
+    let fake_usdc = PaymentToken {
+        symbol: String::from("USDC"),
+        ..fake.clone()
+    };
+
+    assert!(weth.is_eth());
+    assert!(!fake.is_eth());
+    assert!(!fake_usdc.is_stablecoin());
+    assert_eq!(Rates::new(Some(1_600.0)).to_wei(&fake, wei("1")), None);
+}
@@ -0,0 +1,241 @@
+mod common;
+
+use common::{start_down_node, start_node_recording};
+use ethabi::Token;
+use kong_scraper::{
+    reader::{Call, ContractReader, ReadMode, RpcPolicy, MULTICALL3},
+    utils::parse_name,
+};
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use web3::types::Address;
+
+const NAMING: &str = "0x02afd7fd5b1c190506f538b36e7741a2f33d715d";
+// Naming the token reverts.
+const REVERTS: u64 = 13;
+const HEAD: u64 = 42;
+
+fn abi(name: &str) -> ethabi::Contract {
+    ethabi::Contract::load(File::open(format!("src/utils/{}", name)).unwrap()).unwrap()
+}
+
+// What `names(id)` returns: the name left aligned in a bytes32.
+fn name_of(data: &[u8]) -> Option<Vec<u8>> {
+    let id = ethabi::decode(&[ethabi::ParamType::Uint(256)], &data[4..]).unwrap()[0]
+        .clone()
+        .into_uint()
+        .unwrap()
+        .as_u64();
+    if id == REVERTS {
+        return None;
+    }
+    let mut word = format!("Kong{}", id).into_bytes();
+    word.resize(32, 0);
+    Some(word)
+}
+
+fn bytes(value: &Value) -> Vec<u8> {
+    hex::decode(value.as_str().unwrap().trim_start_matches("0x")).unwrap()
+}
+
+#[derive(Default)]
+struct Node {
+    deployed: bool,
+    // Multicalls covering this token fail as a whole.
+    broken_chunk: Option<u64>,
+    multicalls: usize,
+    direct_calls: usize,
+    // Block tag of every call.
+    blocks: BTreeSet<String>,
+    batches: Arc<Mutex<Vec<usize>>>,
+}
+
+async fn start(node: Arc<Mutex<Node>>) -> String {
+    let batches = node.lock().unwrap().batches.clone();
+    let aggregate = abi("multicall3_abi.json")
+        .function("aggregate3")
+        .unwrap()
+        .clone();
+    start_node_recording(
+        Arc::new(move |method, params| {
+            let mut node = node.lock().unwrap();
+            if let Some(block) = params.get(1).and_then(Value::as_str) {
+                node.blocks.insert(block.to_string());
+            }
+            match method {
+                "eth_blockNumber" => Ok(json!(format!("0x{:x}", HEAD))),
+                "eth_getCode" => Ok(json!(if node.deployed { "0x6080" } else { "0x" })),
+                "eth_call" if params[0]["to"] == MULTICALL3.to_lowercase() => {
+                    node.multicalls += 1;
+                    let input = aggregate
+                        .decode_input(&bytes(&params[0]["data"])[4..])
+                        .unwrap();
+                    let calls = input[0].clone().into_array().unwrap();
+                    let mut results = Vec::new();
+                    for call in calls {
+                        let fields = match call {
+                            Token::Tuple(fields) => fields,
+                            other => panic!("unexpected call {:?}", other),
+                        };
+                        let data = fields[2].clone().into_bytes().unwrap();
+                        if node.broken_chunk.map(encode_name) == Some(data.clone()) {
+                            return Err(String::from("out of gas"));
+                        }
+                        results.push(match name_of(&data) {
+                            Some(word) => Token::Tuple(vec![Token::Bool(true), Token::Bytes(word)]),
+                            None => {
+                                Token::Tuple(vec![Token::Bool(false), Token::Bytes(Vec::new())])
+                            }
+                        });
+                    }
+                    let out = ethabi::encode(&[Token::Array(results)]);
+                    Ok(json!(format!("0x{}", hex::encode(out))))
+                }
+                "eth_call" if params[0]["to"] == NAMING => {
+                    node.direct_calls += 1;
+                    match name_of(&bytes(&params[0]["data"])) {
+                        Some(word) => Ok(json!(format!("0x{}", hex::encode(word)))),
+                        None => Err(String::from("execution reverted")),
+                    }
+                }
+                other => Err(format!("unexpected method {}", other)),
+            }
+        }),
+        batches,
+    )
+    .await
+}
+
+fn reader(urls: &[String]) -> ContractReader {
+    let mut reader = ContractReader::new(urls).unwrap();
+    reader.set_policy(RpcPolicy {
+        batch_size: 4,
+        max_retries: 2,
+        backoff: Duration::ZERO,
+        cooldown: Duration::from_secs(60),
+    });
+    reader
+}
+
+fn encode_name(id: u64) -> Vec<u8> {
+    abi("kong_naming_abi.json")
+        .function("names")
+        .unwrap()
+        .encode_input(&[Token::Uint(id.into())])
+        .unwrap()
+}
+
+fn calls(ids: std::ops::Range<u64>) -> Vec<Call> {
+    let to: Address = NAMING.parse().unwrap();
+    ids.map(|id| Call {
+        to,
+        data: encode_name(id).into(),
+    })
+    .collect()
+}
+
+async fn read_names(reader: &ContractReader, ids: std::ops::Range<u64>) -> Vec<String> {
+    let results = reader
+        .read("names", &calls(ids.clone()), HEAD)
+        .await
+        .unwrap();
+    ids.zip(results.iter())
+        .map(|(id, raw)| parse_name(raw, &(id as i16)))
+        .collect()
+}
+
+fn expected(ids: std::ops::Range<u64>) -> Vec<String> {
+    ids.map(|id| match id {
+        REVERTS => format!("Kong #{}", id),
+        _ => format!("Kong{}", id),
+    })
+    .collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn multicall_reads_in_chunks() {
+    let node = Arc::new(Mutex::new(Node {
+        deployed: true,
+        ..Node::default()
+    }));
+    let mut reader = reader(&[start(node.clone()).await]);
+    reader.set_mode(ReadMode::Multicall);
+    reader.set_chunk_size(4);
+
+    // The reverted call falls back to the default name without failing the rest.
+    assert_eq!(read_names(&reader, 10..20).await, expected(10..20));
+    let node = node.lock().unwrap();
+    assert_eq!((node.multicalls, node.direct_calls), (3, 0));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn batches_when_multicall_isnt_deployed() {
+    let node = Arc::new(Mutex::new(Node::default()));
+    let mut reader = reader(&[start(node.clone()).await]);
+    reader.set_mode(ReadMode::Multicall);
+
+    assert_eq!(read_names(&reader, 10..20).await, expected(10..20));
+    let node = node.lock().unwrap();
+    assert_eq!((node.multicalls, node.direct_calls), (0, 10));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failed_chunk_is_read_with_a_batch() {
+    let node = Arc::new(Mutex::new(Node {
+        deployed: true,
+        broken_chunk: Some(17),
+        ..Node::default()
+    }));
+    let mut reader = reader(&[start(node.clone()).await]);
+    reader.set_mode(ReadMode::Multicall);
+    reader.set_chunk_size(4);
+
+    assert_eq!(read_names(&reader, 10..20).await, expected(10..20));
+    let node = node.lock().unwrap();
+    // Only 14..18 is read call by call.
+    assert_eq!((node.multicalls, node.direct_calls), (3, 4));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn splits_batches_and_pins_block() {
+    let node = Arc::new(Mutex::new(Node::default()));
+    let reader = reader(&[start(node.clone()).await]);
+
+    let block = reader.block_number().await.unwrap();
+    assert_eq!(block, HEAD);
+    assert_eq!(read_names(&reader, 10..20).await, expected(10..20));
+    let node = node.lock().unwrap();
+    assert_eq!(*node.batches.lock().unwrap(), [1, 4, 4, 2]);
+    assert_eq!(node.blocks, BTreeSet::from([String::from("0x2a")]));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fails_over_to_next_endpoint() {
+    let (down, down_hits) = start_down_node().await;
+    let node = Arc::new(Mutex::new(Node::default()));
+    let reader = reader(&[down, start(node.clone()).await]);
+
+    assert_eq!(read_names(&reader, 10..20).await, expected(10..20));
+    assert_eq!(read_names(&reader, 10..20).await, expected(10..20));
+    // Benched after the first failure, so the rest go straight to the fallback.
+    assert_eq!(*down_hits.lock().unwrap(), 1);
+    assert_eq!(node.lock().unwrap().direct_calls, 20);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn read_fails_once_retries_run_out() {
+    let (down, down_hits) = start_down_node().await;
+    let reader = reader(&[down]);
+
+    let err = reader
+        .read("names", &calls(10..12), HEAD)
+        .await
+        .unwrap_err();
+    assert!(err.is_retryable());
+    assert_eq!(*down_hits.lock().unwrap(), 3);
+}
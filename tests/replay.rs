@@ -0,0 +1,89 @@
+mod common;
+
+use common::{test_config, MockOpensea, Reply};
+use kong_scraper::{
+    error::Error,
+    kong_data::ScaperBot,
+    opensea_client::{
+        recorder::{HttpMode, Replayer},
+        OpenseaClient,
+    },
+};
+use std::time::Duration;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn replays_a_recorded_session() {
+    let mock = MockOpensea::new()
+        .events(
+            "created",
+            None,
+            vec![Reply::ok("events_created_page2.json")],
+        )
+        .events("successful", None, vec![Reply::ok("events_empty.json")])
+        .events("cancelled", None, vec![Reply::ok("events_empty.json")])
+        .listings(
+            3,
+            vec![Reply::status(429), Reply::ok("listings_basic.json")],
+        );
+    let url = mock.start().await;
+    let mut config = test_config("record", &url);
+    let recording = format!("{}.jsonl", &config.cache_path);
+    config.opensea_record = Some(recording.clone());
+    let _ = std::fs::remove_file(&config.cache_path);
+    let mut recorded = ScaperBot::init(&config).await.unwrap();
+    recorded.update_prices().await.unwrap();
+
+    // Nothing listens on the base url, so every response has to come from the recording.
+    let mut config = test_config("replay", "http://127.0.0.1:1");
+    config.opensea_replay = Some(recording);
+    let _ = std::fs::remove_file(&config.cache_path);
+    let mut replayed = ScaperBot::init(&config).await.unwrap();
+    replayed.update_prices().await.unwrap();
+
+    let sales = &replayed.get_all().data()[&3].current_sales;
+    assert_eq!(sales.len(), 2);
+    assert_eq!(sales[1].price_eth(), 2.0);
+    assert_eq!(mock.listing_hits(3), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn replays_checked_in_recording() {
+    let mut config = test_config("checked-in", "http://127.0.0.1:1");
+    config.opensea_replay = Some(String::from("tests/fixtures/recordings/price_update.jsonl"));
+    let _ = std::fs::remove_file(&config.cache_path);
+    let mut bot = ScaperBot::init(&config).await.unwrap();
+
+    bot.update_prices().await.unwrap();
+
+    assert_eq!(bot.get_all().data()[&3].current_sales.len(), 2);
+    assert_eq!(bot.get_all().num_listed(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn replay_fails_on_unrecorded_request() {
+    let mut config = test_config("unrecorded", "http://127.0.0.1:1");
+    config.opensea_replay = Some(String::from("tests/fixtures/recordings/price_update.jsonl"));
+    let _ = std::fs::remove_file(&config.cache_path);
+    let mut bot = ScaperBot::init(&config).await.unwrap();
+    bot.update_prices().await.unwrap();
+
+    // The second run asks for events after the first run, which were never recorded.
+    let err = bot.update_prices().await.unwrap_err();
+    assert!(matches!(err, Error::Transport(_)));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ping_skips_opensea_when_replaying() {
+    let mut client = OpenseaClient::new(
+        &[String::from("key")],
+        "http://127.0.0.1:1",
+        Duration::ZERO,
+        Duration::ZERO,
+    );
+    assert!(client.ping().await.is_err());
+
+    client.set_http_mode(HttpMode::Replay(
+        Replayer::load("tests/fixtures/recordings/price_update.jsonl").unwrap(),
+    ));
+    client.ping().await.unwrap();
+}
@@ -0,0 +1,164 @@
+mod common;
+
+use common::{block, start_node};
+use ethabi::Token;
+use kong_scraper::{
+    error::Error,
+    reader::{ContractReader, TokenReader, TokenState},
+};
+use serde_json::{json, Value};
+use std::{fs::File, sync::Arc};
+
+const GENESIS_TS: u64 = 1_600_000_000;
+const HEAD: u64 = 1_000;
+// Kongs were renamed here.
+const RENAMED_AT: u64 = 500;
+// Every Kong changed hands here.
+const SOLD_AT: u64 = 700;
+// Token 3 didn't exist before this block.
+const MINTED_AT: u64 = 300;
+// The naming contract was deployed here, reads before it come back empty.
+const NAMING_AT: u64 = 100;
+
+// Blocks are twelve seconds apart, except for a five minute gap after block 600.
+fn mined_at(number: u64) -> u64 {
+    match number {
+        0..=600 => GENESIS_TS + number * 12,
+        _ => GENESIS_TS + number * 12 + 300,
+    }
+}
+
+fn hex(value: &Value) -> u64 {
+    u64::from_str_radix(value.as_str().unwrap().trim_start_matches("0x"), 16).unwrap()
+}
+
+fn word(bytes: &[u8]) -> Value {
+    json!(format!("0x{}", hex::encode(bytes)))
+}
+
+async fn start_chain() -> String {
+    let naming =
+        ethabi::Contract::load(File::open("src/utils/kong_naming_abi.json").unwrap()).unwrap();
+    let erc721 = ethabi::Contract::load(File::open("src/utils/erc721_abi.json").unwrap()).unwrap();
+    let names = naming.function("names").unwrap().short_signature();
+    let bios = naming.function("bios").unwrap().short_signature();
+    let owner_of = erc721.function("ownerOf").unwrap().short_signature();
+    start_node(Arc::new(move |method, params| match method {
+        "eth_blockNumber" => Ok(json!(format!("0x{:x}", HEAD))),
+        "eth_getBlockByNumber" => {
+            let n = hex(&params[0]);
+            match n <= HEAD {
+                true => Ok(block(n, &format!("0x{:064x}", n), mined_at(n))),
+                false => Ok(Value::Null),
+            }
+        }
+        "eth_call" => {
+            let at = hex(&params[1]);
+            let data =
+                hex::decode(params[0]["data"].as_str().unwrap().trim_start_matches("0x")).unwrap();
+            let id = ethabi::decode(&[ethabi::ParamType::Uint(256)], &data[4..]).unwrap()[0]
+                .clone()
+                .into_uint()
+                .unwrap()
+                .as_u64();
+            if id == 3 && at < MINTED_AT {
+                return Err(String::from("execution reverted"));
+            }
+            match &data[..4] {
+                selector if at < NAMING_AT && (selector == names || selector == bios) => {
+                    Ok(json!("0x"))
+                }
+                selector if selector == names => {
+                    let prefix = if at < RENAMED_AT { "Old" } else { "New" };
+                    let mut name = format!("{}{}", prefix, id).into_bytes();
+                    name.resize(32, 0);
+                    Ok(word(&name))
+                }
+                selector if selector == owner_of => {
+                    let holder = if at < SOLD_AT { 0xaa } else { 0xbb };
+                    Ok(word(&ethabi::encode(&[Token::Address(
+                        [holder; 20].into(),
+                    )])))
+                }
+                // No bios were ever set.
+                _ => Err(String::from("execution reverted")),
+            }
+        }
+        other => Err(format!("unexpected method {}", other)),
+    }))
+    .await
+}
+
+fn state(token_id: i16, name: &str, owner: Option<u8>) -> TokenState {
+    TokenState {
+        token_id,
+        name: name.to_string(),
+        bio: None,
+        owner: owner.map(|b| format!("0x{}", hex::encode([b; 20]))),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn finds_block_by_timestamp() {
+    let reader = ContractReader::new(&[start_chain().await]).unwrap();
+
+    assert_eq!(reader.block_at(mined_at(250)).await.unwrap(), Some(250));
+    assert_eq!(
+        reader.block_at(mined_at(250) + 11).await.unwrap(),
+        Some(250)
+    );
+    // Inside the gap the last block before it is current.
+    assert_eq!(
+        reader.block_at(mined_at(600) + 200).await.unwrap(),
+        Some(600)
+    );
+    assert_eq!(reader.block_at(mined_at(601)).await.unwrap(), Some(601));
+    assert_eq!(reader.block_at(GENESIS_TS - 1).await.unwrap(), None);
+    assert_eq!(
+        reader.block_at(mined_at(HEAD) + 60).await.unwrap(),
+        Some(HEAD)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn snapshots_state_at_past_blocks() {
+    let reader = ContractReader::new(&[start_chain().await]).unwrap();
+    let block = reader.block_at(mined_at(200) + 5).await.unwrap().unwrap();
+    let tokens = TokenReader::new(reader).unwrap();
+
+    let before = tokens.snapshot(&[1, 3], block).await.unwrap();
+    assert_eq!((before.block, before.timestamp), (200, mined_at(200)));
+    assert_eq!(
+        before.tokens,
+        [state(1, "Old1", Some(0xaa)), state(3, "Kong #3", None)]
+    );
+
+    let after = tokens.snapshot(&[1, 3], 800).await.unwrap();
+    assert_eq!(after.timestamp, mined_at(800));
+    assert_eq!(
+        after.tokens,
+        [state(1, "New1", Some(0xbb)), state(3, "New3", Some(0xbb))]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn snapshot_of_unmined_block_is_an_error() {
+    let tokens = TokenReader::new(ContractReader::new(&[start_chain().await]).unwrap()).unwrap();
+
+    let err = tokens.snapshot(&[1, 3], HEAD + 1).await.unwrap_err();
+    assert!(matches!(err, Error::UnknownBlock(n) if n == HEAD + 1));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn snapshot_before_naming_contract_has_default_names() {
+    let tokens = TokenReader::new(ContractReader::new(&[start_chain().await]).unwrap()).unwrap();
+
+    let before = tokens.snapshot(&[1, 2], NAMING_AT - 1).await.unwrap();
+    assert_eq!(
+        before.tokens,
+        [
+            state(1, "Kong #1", Some(0xaa)),
+            state(2, "Kong #2", Some(0xaa))
+        ]
+    );
+}